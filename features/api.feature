@@ -7,21 +7,142 @@ Feature: divvun-api
     When I go to the endpoint `/languages`
     Then I get back a JSON object with available languages and their titles
 
+  Scenario: Retrieving language metadata with English names
+    When I go to the endpoint `/languages?ui=en-GB`
+    Then I get back metadata for the `se` language named `Northern Sami`
+
+  Scenario: Retrieving language metadata with names in the language itself
+    When I go to the endpoint `/languages?ui=sme`
+    Then I get back metadata for the `se` language named `davvisámegiella`
+
+  Scenario: Checking the health of the server
+    When I go to the endpoint `/health`
+    Then I get back a JSON object with the status `ok`
+
+  Scenario: Checking the readiness of the server
+    When I go to the endpoint `/ready`
+    Then I get back a JSON object saying the server is ready
+
+  Scenario: Retrieving the status of the loaded models
+    When I go to the endpoint `/status`
+    Then I get back a JSON object with a running `se` speller, grammar checker and hyphenator
+
+  Scenario: Retrieving Prometheus metrics
+    When I go to the endpoint `/speller/se` with appropriate data
+    And I go to the metrics endpoint
+    Then I get back metrics with the request count for the `speller` endpoint and `se` language
+
+  Scenario: Counting requests for languages without a model in the metrics
+    When I go to the endpoint `/speller/en` for not loaded language
+    And I go to the metrics endpoint
+    Then I get back metrics with the request count for the `speller` endpoint and `unknown` language
+
+  Scenario: Counting failed requests per language in the metrics
+    When I go to the endpoint `/speller/se` asking for 0 suggestions
+    And I go to the metrics endpoint
+    Then I get back metrics with a `unprocessable_input` error for the `speller` endpoint and `se` language
+
+  Scenario: Listing models through the admin API without a token
+    When I get the admin endpoint `/admin/models` without a token
+    Then the error has the status 401 and the code `unauthorized`
+
+  Scenario: Reloading a model through the admin API
+    When I post to the admin endpoint `/admin/models/spelling/se/reload`
+    Then I get back a `reload` model action with a running `se` model
+
+  Scenario: Reloading a model through the admin API with a lower case scheme
+    When I post to the admin endpoint `/admin/models/spelling/se/reload` with the token as `bearer` credentials
+    Then I get back a `reload` model action with a running `se` model
+
+  Scenario: Unloading a model through the admin API with another scheme than Bearer
+    When I delete the admin endpoint `/admin/models/spelling/se` with the token as `Basic` credentials
+    Then the error has the status 401 and the code `unauthorized`
+
+  Scenario: Loading a model through the admin API with a wrong token
+    When I put the model file `tests/resources/data_files/hyphenation/se.hfstol` to the admin endpoint `/admin/models/hyphenation/xx` with a wrong token
+    Then the error has the status 401 and the code `unauthorized`
+
+  Scenario: Loading a model through the admin API from a missing file
+    When I put the model file `tests/resources/data_files/hyphenation/missing.hfstol` to the admin endpoint `/admin/models/hyphenation/xx`
+    Then the error has the status 422 and the code `unprocessable_input`
+
+  Scenario: Loading a model through the admin API from a file of another type
+    When I put the model file `tests/resources/data_files/spelling/se.zhfst` to the admin endpoint `/admin/models/hyphenation/xx`
+    Then the error has the status 422 and the code `unprocessable_input`
+
+  Scenario: Loading a model of an unknown type through the admin API
+    When I put the model file `tests/resources/data_files/hyphenation/se.hfstol` to the admin endpoint `/admin/models/dictionary/xx`
+    Then the error has the status 400 and the code `invalid_input`
+
+  Scenario: Loading and unloading a model through the admin API
+    When I put the model file `tests/resources/data_files/hyphenation/se.hfstol` to the admin endpoint `/admin/models/hyphenation/xx`
+    Then I get back a `load` model action with a running `xx` model
+    When I delete the admin endpoint `/admin/models/hyphenation/xx`
+    Then I get back an `unload` model action for the `xx` model
+
+  Scenario: Receiving model events while a model is reloaded
+    When I post to the admin endpoint `/admin/models/spelling/se/reload` while listening to `/events`
+    Then I receive a `ModelReloaded` event for the `se` spelling model
+
   Scenario: Checking spelling for `se` language
     When I go to the endpoint `/speller/se` with appropriate data
     Then I get back a SpellerResponse with suggestions for each word
 
+  Scenario: Checking spelling for `se` language without correct words
+    When I go to the endpoint `/speller/se` excluding correct words
+    Then I get back a SpellerResponse with only misspelled words
+
+  Scenario: Checking spelling for `se` language with a single suggestion
+    When I go to the endpoint `/speller/se` asking for 1 suggestion
+    Then I get back a SpellerResponse with at most 1 suggestion per word
+
+  Scenario: Checking spelling for `se` language past the suggestion time budget
+    When I go to the endpoint `/speller/se` with a time budget of 0 ms
+    Then I get back a SpellerResponse checking each word without suggestions
+
+  Scenario: Checking spelling with the ISO 639-3 tag of the `se` language
+    When I go to the endpoint `/speller/sme` with another tag for the language
+    Then I get back a SpellerResponse from the `se` speller
+
+  Scenario: Checking spelling with an uppercase regional tag of the `se` language
+    When I go to the endpoint `/speller/SE-NO` with another tag for the language
+    Then I get back a SpellerResponse from the `se` speller
+
+  Scenario: Retrieving speller info for `se` language
+    When I go to the endpoint `/speller/sme/info`
+    Then I get back info about the `se` speller
+
+  Scenario: Retrieving speller info for not loaded `en` language
+    When I get the endpoint `/speller/en/info` for not loaded language
+    Then I get back an ApiError with the message `No speller available for language en`
+    And the error has the status 404 and the code `language_not_found`
+
   Scenario: Checking grammar for `se` language
     When I go to the endpoint `/grammar/se` with appropriate data
     Then I get back a GramcheckResponse with `typo` and `double-space-before` error codes
 
+  Scenario: Checking grammar for `se` language with a rule turned off
+    When I go to the endpoint `/grammar/se` ignoring the `double-space-before` error tag
+    Then I get back a GramcheckResponse with only the `typo` error code
+
+  Scenario: Checking grammar for multi-paragraph `se` text
+    When I go to the endpoint `/grammar/se` with multiple paragraphs of data
+    Then I get back a GramcheckResponse with errors for each paragraph
+
   Scenario: Checking spelling for not loaded `en` language
     When I go to the endpoint `/speller/en` for not loaded language
     Then I get back an ApiError with the message `No speller available for language en`
+    And the error has the status 404 and the code `language_not_found`
 
   Scenario: Checking grammar for not loaded `en` language
     When I go to the endpoint `/grammar/en` for not loaded language
     Then I get back an ApiError with the message `No grammar checker available for language en`
+    And the error has the status 404 and the code `language_not_found`
+
+  Scenario: Retrieving grammar preferences for not loaded `en` language
+    When I get the endpoint `/preferences/grammar/en` for not loaded language
+    Then I get back an ApiError with the message `No grammar checker available for language en`
+    And the error has the status 404 and the code `language_not_found`
 
   Scenario: Checking hyphenation for `se` language
     When I go to the endpoint `/hyphenation/se` with appropriate data
@@ -30,3 +151,35 @@ Feature: divvun-api
   Scenario: Checking GraphQL response for `se` language
     When I go to the endpoint `/graphql` with an appropriate GraphQL query
     Then I get back a JSON object with a Speller and Grammar, and Hyphenation response
+
+  Scenario: Checking GraphQL response with a field selected twice
+    When I go to the endpoint `/graphql` with a GraphQL query selecting the speller twice
+    Then I get back the results of both speller selections
+
+  Scenario: Checking GraphQL response for a query with variables and a fragment
+    When I go to the endpoint `/graphql` with a GraphQL query using variables and a fragment
+    Then I get back the results of both speller selections
+
+  Scenario: Reloading a model through a GraphQL mutation
+    When I send a GraphQL mutation reloading the `se` speller with the admin token
+    Then I get back a `reload` GraphQL model action for the `se` speller
+
+  Scenario: Reloading a model through a GraphQL mutation with a wrong token
+    When I send a GraphQL mutation reloading the `se` speller with a wrong token
+    Then I get back a GraphQL error with the code `unauthorized`
+
+  Scenario: Retrieving languages, grammar preferences and models through GraphQL
+    When I go to the endpoint `/graphql` with a GraphQL query for languages, preferences and models
+    Then I get back the `se` language, its grammar preferences and its models
+
+  Scenario: Checking a batch of texts
+    When I post a batch of texts to the endpoint `/batch`
+    Then I get back the results of each text by its id
+
+  Scenario: Checking a batch of texts through GraphQL
+    When I go to the endpoint `/graphql` with a GraphQL query checking a batch of texts
+    Then I get back the results of each text with its id
+
+  Scenario: Checking edits to a document in a WebSocket session
+    When I open a check session for `se` and append a misspelled word
+    Then I get back the speller results of the edited paragraph only
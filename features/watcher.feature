@@ -9,6 +9,18 @@ Feature: watcher
     And I go to the speller endpoint for `smj` with appropriate data
     Then I get back a SpellerResponse with some suggestions
 
+  Scenario: Retrieving speller information for a language moved into place
+    Given I have the `smj.zhfst` file available
+    When I move the `smj.zhfst` file into the `spelling` folder through a temporary file
+    And I go to the speller endpoint for `smj` with appropriate data
+    Then I get back a SpellerResponse with some suggestions
+
+  Scenario: Retrieving speller information for a speller variant
+    Given I have the `se.zhfst` file available
+    When I load the `se.zhfst` file into the `spelling` folder as `se-x-school.zhfst`
+    And I go to the speller endpoint for `se` with the `school` variant
+    Then I get back a SpellerResponse and remove the `se-x-school.zhfst` file from the `spelling` folder
+
   Scenario: Retrieving grammar information for a newly added language
     Given I have the `smj.zcheck` file available
     When I load the `smj.zcheck` file into the `grammar` folder
@@ -29,6 +41,13 @@ Feature: watcher
     Then I get back an ApiError with the message `No grammar checker available for language se`
     And I put the removed `se.zcheck` file back into the `grammar` folder
 
+  Scenario: Keeping a speller serving when its file is replaced with a corrupt one
+    Given I have the `se.zhfst` file available
+    When I replace the `se.zhfst` file in the `spelling` folder with garbage through a temporary file
+    And I go to the endpoint `/speller/se` with appropriate data
+    Then I get back a SpellerResponse with suggestions for each word
+    And I put the removed `se.zhfst` file back into the `spelling` folder
+
   Scenario: Retrieving available languages for an ISO 639-3 language loaded at runtime
     Given I have the `smj.zcheck` file available
     When I load the `smj.zcheck` file into the `grammar` folder
@@ -1,17 +1,419 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use directories::ProjectDirs;
+use failure::Fail;
+use log::warn;
 use serde::Deserialize;
 
-use std::path::PathBuf;
+use crate::language::data_files::{get_data_files, DataFileType};
+
+const ENV_PREFIX: &str = "DIVVUN_API_";
 
-#[derive(Debug, Deserialize)]
+#[derive(Fail, Debug)]
+pub enum ConfigError {
+    #[fail(display = "Failed to read config file {}: {}", _0, _1)]
+    Read(String, std::io::Error),
+    #[fail(display = "Failed to parse config file {}: {}", _0, _1)]
+    Parse(String, toml::de::Error),
+    #[fail(display = "Missing required config key `{}`", _0)]
+    Missing(&'static str),
+    #[fail(display = "Invalid value for `{}`: {}", _0, _1)]
+    Invalid(&'static str, String),
+}
+
+/// The config as it appears in the TOML file. Every key can be overridden by an environment
+/// variable named after it, e.g. `DIVVUN_API_WORKERS` for `workers`.
+#[derive(Debug, Default, Deserialize)]
 pub struct TomlConfig {
-    pub addr: String,
+    pub addr: Option<String>,
     pub data_file_dir: Option<PathBuf>,
+    pub spelling_dir: Option<PathBuf>,
+    pub grammar_dir: Option<PathBuf>,
+    pub hyphenation_dir: Option<PathBuf>,
+    pub recursive_data_dirs: Option<bool>,
     pub watcher_interval_ms: Option<u64>,
+    pub workers: Option<usize>,
+    pub cors_origins: Option<Vec<String>>,
+    pub max_body_bytes: Option<usize>,
+    pub admin_token: Option<String>,
+    pub grammar_pool_size: Option<usize>,
+    pub grammar_queue_size: Option<usize>,
+    pub grammar_timeout_ms: Option<u64>,
+    pub batch_concurrency: Option<usize>,
+    pub batch_max_inputs: Option<usize>,
+    pub session_concurrency: Option<usize>,
+    pub session_idle_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub grammar_pool_sizes: HashMap<String, usize>,
+    /// Keys that aren't known, such as those of older versions, which are warned about rather
+    /// than refused
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, toml::Value>,
+}
+
+impl TomlConfig {
+    pub fn from_toml_str(config: &str, config_file: &str) -> Result<Self, ConfigError> {
+        let config: TomlConfig =
+            toml::from_str(config).map_err(|e| ConfigError::Parse(config_file.to_owned(), e))?;
+
+        for key in config.unknown.keys() {
+            warn!("Ignoring unknown config key `{}` in {}", key, config_file);
+        }
+
+        Ok(config)
+    }
+
+    /// Replaces values with those set in `DIVVUN_API_*` environment variables
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(v) = env_var("addr") {
+            self.addr = Some(v);
+        }
+        if let Some(v) = env_var("data_file_dir") {
+            self.data_file_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env_var("spelling_dir") {
+            self.spelling_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env_var("grammar_dir") {
+            self.grammar_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env_var("hyphenation_dir") {
+            self.hyphenation_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env_var("recursive_data_dirs") {
+            self.recursive_data_dirs = Some(parse("recursive_data_dirs", &v)?);
+        }
+        if let Some(v) = env_var("watcher_interval_ms") {
+            self.watcher_interval_ms = Some(parse("watcher_interval_ms", &v)?);
+        }
+        if let Some(v) = env_var("workers") {
+            self.workers = Some(parse("workers", &v)?);
+        }
+        if let Some(v) = env_var("cors_origins") {
+            self.cors_origins = Some(split_list(&v).map(str::to_owned).collect());
+        }
+        if let Some(v) = env_var("max_body_bytes") {
+            self.max_body_bytes = Some(parse("max_body_bytes", &v)?);
+        }
+        if let Some(v) = env_var("admin_token") {
+            self.admin_token = Some(v);
+        }
+        if let Some(v) = env_var("grammar_pool_size") {
+            self.grammar_pool_size = Some(parse("grammar_pool_size", &v)?);
+        }
+        if let Some(v) = env_var("grammar_queue_size") {
+            self.grammar_queue_size = Some(parse("grammar_queue_size", &v)?);
+        }
+        if let Some(v) = env_var("grammar_timeout_ms") {
+            self.grammar_timeout_ms = Some(parse("grammar_timeout_ms", &v)?);
+        }
+        if let Some(v) = env_var("batch_concurrency") {
+            self.batch_concurrency = Some(parse("batch_concurrency", &v)?);
+        }
+        if let Some(v) = env_var("batch_max_inputs") {
+            self.batch_max_inputs = Some(parse("batch_max_inputs", &v)?);
+        }
+        if let Some(v) = env_var("session_concurrency") {
+            self.session_concurrency = Some(parse("session_concurrency", &v)?);
+        }
+        if let Some(v) = env_var("session_idle_timeout_ms") {
+            self.session_idle_timeout_ms = Some(parse("session_idle_timeout_ms", &v)?);
+        }
+        // In the form `se=8,smj=2`
+        if let Some(v) = env_var("grammar_pool_sizes") {
+            self.grammar_pool_sizes = split_list(&v)
+                .map(|entry| {
+                    let mut parts = entry.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(language), Some(size)) => Ok((
+                            language.trim().to_owned(),
+                            parse("grammar_pool_sizes", size)?,
+                        )),
+                        _ => Err(ConfigError::Invalid(
+                            "grammar_pool_sizes",
+                            format!("expected `language=size`, got `{}`", entry),
+                        )),
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
 pub struct Config {
     pub addr: String,
     pub data_file_dir: PathBuf,
+    /// Directories that replace the `spelling`, `grammar` or `hyphenation` subdirectory of
+    /// `data_file_dir` for one type of data file
+    pub spelling_dir: Option<PathBuf>,
+    pub grammar_dir: Option<PathBuf>,
+    pub hyphenation_dir: Option<PathBuf>,
+    /// Whether data files are also looked for in subdirectories, e.g. `spelling/se/se.zhfst`
+    pub recursive_data_dirs: bool,
     pub watcher_interval_ms: u64,
+    pub workers: usize,
+    /// Origins allowed to make cross-origin requests. Any origin is allowed when empty.
+    pub cors_origins: Vec<String>,
+    /// Largest JSON request body accepted, in bytes
+    pub max_body_bytes: usize,
+    /// Bearer token required by the admin API, which is disabled when unset
+    pub admin_token: Option<String>,
+    pub grammar_pool: GramcheckPoolConfig,
+    /// How many checks of a batch request are sent to the backends at a time
+    pub batch_concurrency: usize,
+    /// Largest number of texts accepted in a single batch request
+    pub batch_max_inputs: usize,
+    /// How many checks of a WebSocket session are sent to the backends at a time
+    pub session_concurrency: usize,
+    /// How long a WebSocket session may go without hearing from its client before it is closed
+    pub session_idle_timeout_ms: u64,
+}
+
+impl Config {
+    /// Builds and validates the config, falling back to defaults for keys that aren't set
+    pub fn from_toml(toml_config: TomlConfig) -> Result<Self, ConfigError> {
+        let grammar_pool = GramcheckPoolConfig::from_toml(&toml_config);
+
+        let config = Config {
+            addr: toml_config.addr.ok_or(ConfigError::Missing("addr"))?,
+            data_file_dir: toml_config
+                .data_file_dir
+                .unwrap_or_else(default_data_file_dir),
+            spelling_dir: toml_config.spelling_dir,
+            grammar_dir: toml_config.grammar_dir,
+            hyphenation_dir: toml_config.hyphenation_dir,
+            recursive_data_dirs: toml_config.recursive_data_dirs.unwrap_or(false),
+            watcher_interval_ms: toml_config.watcher_interval_ms.unwrap_or(1000),
+            workers: toml_config.workers.unwrap_or(4),
+            cors_origins: toml_config.cors_origins.unwrap_or_default(),
+            max_body_bytes: toml_config.max_body_bytes.unwrap_or(256 * 1024),
+            admin_token: toml_config.admin_token.filter(|token| !token.is_empty()),
+            grammar_pool,
+            batch_concurrency: toml_config.batch_concurrency.unwrap_or(8),
+            batch_max_inputs: toml_config.batch_max_inputs.unwrap_or(10_000),
+            session_concurrency: toml_config.session_concurrency.unwrap_or(4),
+            session_idle_timeout_ms: toml_config.session_idle_timeout_ms.unwrap_or(60_000),
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn data_dir(&self, data_type: DataFileType) -> PathBuf {
+        let dir = match data_type {
+            DataFileType::Spelling => &self.spelling_dir,
+            DataFileType::Grammar => &self.grammar_dir,
+            DataFileType::Hyphenation => &self.hyphenation_dir,
+        };
+
+        match dir {
+            Some(dir) => dir.clone(),
+            None => self.data_file_dir.join(data_type.as_dir()),
+        }
+    }
+
+    pub fn data_files(&self, data_type: DataFileType) -> std::io::Result<Vec<PathBuf>> {
+        get_data_files(
+            &self.data_dir(data_type),
+            data_type,
+            self.recursive_data_dirs,
+        )
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !is_valid_addr(&self.addr) {
+            return Err(ConfigError::Invalid(
+                "addr",
+                format!("`{}` is not in the form `host:port`", self.addr),
+            ));
+        }
+
+        non_zero("watcher_interval_ms", self.watcher_interval_ms as usize)?;
+        non_zero("workers", self.workers)?;
+        non_zero("max_body_bytes", self.max_body_bytes)?;
+        non_zero("grammar_pool_size", self.grammar_pool.default_size)?;
+        non_zero("grammar_queue_size", self.grammar_pool.queue_size)?;
+        non_zero("grammar_timeout_ms", self.grammar_pool.timeout_ms as usize)?;
+        non_zero("batch_concurrency", self.batch_concurrency)?;
+        non_zero("batch_max_inputs", self.batch_max_inputs)?;
+        non_zero("session_concurrency", self.session_concurrency)?;
+        non_zero(
+            "session_idle_timeout_ms",
+            self.session_idle_timeout_ms as usize,
+        )?;
+
+        for (language, size) in &self.grammar_pool.sizes {
+            if *size == 0 {
+                return Err(ConfigError::Invalid(
+                    "grammar_pool_sizes",
+                    format!("pool size for `{}` must be at least 1", language),
+                ));
+            }
+        }
+
+        for origin in &self.cors_origins {
+            if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                return Err(ConfigError::Invalid(
+                    "cors_origins",
+                    format!("`{}` is not an http(s) origin", origin),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How many `divvun-checker` processes are kept per grammar language, and how they are run
+#[derive(Clone, Debug)]
+pub struct GramcheckPoolConfig {
+    pub default_size: usize,
+    pub sizes: HashMap<String, usize>,
+    /// Maximum number of requests waiting on a single language's pool before new ones are refused
+    pub queue_size: usize,
+    /// How long a `divvun-checker` process may take to answer a single paragraph before it is
+    /// considered hung and restarted
+    pub timeout_ms: u64,
+}
+
+impl GramcheckPoolConfig {
+    pub fn from_toml(toml_config: &TomlConfig) -> Self {
+        let default = Self::default();
+
+        GramcheckPoolConfig {
+            default_size: toml_config
+                .grammar_pool_size
+                .unwrap_or(default.default_size),
+            sizes: toml_config.grammar_pool_sizes.clone(),
+            queue_size: toml_config.grammar_queue_size.unwrap_or(default.queue_size),
+            timeout_ms: toml_config.grammar_timeout_ms.unwrap_or(default.timeout_ms),
+        }
+    }
+
+    pub fn size_for(&self, language: &str) -> usize {
+        self.sizes
+            .get(language)
+            .copied()
+            .unwrap_or(self.default_size)
+            .max(1)
+    }
+}
+
+impl Default for GramcheckPoolConfig {
+    fn default() -> Self {
+        GramcheckPoolConfig {
+            default_size: 1,
+            sizes: HashMap::new(),
+            queue_size: 64,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+fn default_data_file_dir() -> PathBuf {
+    match ProjectDirs::from("no", "uit", "api-giellalt") {
+        Some(v) => v.data_dir().to_owned(),
+        None => PathBuf::from("./"),
+    }
+}
+
+fn env_var(key: &str) -> Option<String> {
+    env::var(format!("{}{}", ENV_PREFIX, key.to_uppercase()))
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+fn parse<T>(key: &'static str, value: &str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| ConfigError::Invalid(key, format!("`{}`: {}", value, e)))
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Checks that an address is an IP address or a host name with a port. Host names are only
+/// resolved when binding, which reports any failure to do so.
+fn is_valid_addr(addr: &str) -> bool {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return true;
+    }
+
+    let mut parts = addr.rsplitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(port), Some(host)) => {
+            !host.is_empty() && !host.contains(':') && port.parse::<u16>().is_ok()
+        }
+        _ => false,
+    }
+}
+
+fn non_zero(key: &'static str, value: usize) -> Result<(), ConfigError> {
+    if value == 0 {
+        Err(ConfigError::Invalid(
+            key,
+            "must be greater than 0".to_owned(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_defaults_and_validation() {
+        let toml_config = TomlConfig::from_toml_str("addr = '127.0.0.1:8080'", "test").unwrap();
+        let config = Config::from_toml(toml_config).unwrap();
+        assert_eq!(config.workers, 4);
+        assert_eq!(config.watcher_interval_ms, 1000);
+        assert_eq!(config.batch_concurrency, 8);
+        assert_eq!(config.session_concurrency, 4);
+        assert_eq!(config.session_idle_timeout_ms, 60_000);
+        assert_eq!(
+            config.data_dir(DataFileType::Grammar),
+            config.data_file_dir.join("grammar")
+        );
+
+        let toml_config =
+            TomlConfig::from_toml_str("addr = '127.0.0.1:8080'\nworkers = 0", "test").unwrap();
+        assert!(Config::from_toml(toml_config).is_err());
+
+        assert!(Config::from_toml(TomlConfig::default()).is_err());
+
+        // Unknown keys are ignored
+        let toml_config = TomlConfig::from_toml_str("adr = '127.0.0.1:8080'", "test").unwrap();
+        assert!(toml_config.unknown.contains_key("adr"));
+        assert!(toml_config.addr.is_none());
+    }
+
+    #[test]
+    fn test_addr_validation() {
+        assert!(is_valid_addr("127.0.0.1:8080"));
+        assert!(is_valid_addr("[::1]:8080"));
+        // Host names are not resolved
+        assert!(is_valid_addr("localhost:8080"));
+        assert!(is_valid_addr("api.invalid:8080"));
+
+        assert!(!is_valid_addr("127.0.0.1"));
+        assert!(!is_valid_addr(":8080"));
+        assert!(!is_valid_addr("localhost:http"));
+        assert!(!is_valid_addr("::1:8080"));
+    }
 }
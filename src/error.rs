@@ -1,39 +1,125 @@
 use std::io;
 
-use actix_web::error::ResponseError;
+use actix_web::error::{BlockingError, ResponseError};
+use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 use failure::Fail;
 use log::error;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 
-#[derive(Fail, Debug, Deserialize, Serialize, Clone)]
-#[fail(display = "api error")]
-pub struct ApiError {
+#[derive(Fail, Debug, Clone)]
+pub enum ApiError {
+    /// No model is loaded for the requested language
+    #[fail(display = "{}", _0)]
+    LanguageNotFound(String),
+    /// The request could not be parsed
+    #[fail(display = "{}", _0)]
+    InvalidInput(String),
+    /// The request lacks valid admin credentials
+    #[fail(display = "{}", _0)]
+    Unauthorized(String),
+    /// The request was well-formed but contains values that cannot be used
+    #[fail(display = "{}", _0)]
+    UnprocessableInput(String),
+    /// A backend crashed or returned something unexpected
+    #[fail(display = "{}", _0)]
+    BackendFailure(String),
+    /// A backend is busy, quarantined or not running
+    #[fail(display = "{}", _0)]
+    Unavailable(String),
+    /// A backend did not answer in time
+    #[fail(display = "{}", _0)]
+    Timeout(String),
+    #[fail(display = "{}", _0)]
+    Internal(String),
+}
+
+/// The JSON body of every error response
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ApiErrorResponse {
+    pub code: String,
     pub message: String,
 }
 
+impl ApiError {
+    /// A stable identifier for the kind of error, for clients to match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::LanguageNotFound(_) => "language_not_found",
+            ApiError::InvalidInput(_) => "invalid_input",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::UnprocessableInput(_) => "unprocessable_input",
+            ApiError::BackendFailure(_) => "backend_failure",
+            ApiError::Unavailable(_) => "backend_unavailable",
+            ApiError::Timeout(_) => "backend_timeout",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::LanguageNotFound(message)
+            | ApiError::InvalidInput(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::UnprocessableInput(message)
+            | ApiError::BackendFailure(message)
+            | ApiError::Unavailable(message)
+            | ApiError::Timeout(message)
+            | ApiError::Internal(message) => message,
+        }
+    }
+
+    pub fn to_response(&self) -> ApiErrorResponse {
+        ApiErrorResponse {
+            code: self.code().to_owned(),
+            message: self.message().to_owned(),
+        }
+    }
+}
+
 impl From<io::Error> for ApiError {
     fn from(item: io::Error) -> Self {
-        ApiError {
-            message: item.to_string(),
-        }
+        ApiError::BackendFailure(item.to_string())
     }
 }
 
 impl From<std::string::FromUtf8Error> for ApiError {
     fn from(item: std::string::FromUtf8Error) -> Self {
-        ApiError {
-            message: item.to_string(),
+        ApiError::BackendFailure(item.to_string())
+    }
+}
+
+impl From<BlockingError<ApiError>> for ApiError {
+    fn from(item: BlockingError<ApiError>) -> Self {
+        match item {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => {
+                ApiError::Internal("A blocking task was canceled".to_owned())
+            }
         }
     }
 }
 
 impl ResponseError for ApiError {
-    fn render_response(&self) -> HttpResponse {
-        error!("{}", self.message);
-        return HttpResponse::InternalServerError()
+    fn error_response(&self) -> HttpResponse {
+        let status = match self {
+            ApiError::LanguageNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::UnprocessableInput(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::BackendFailure(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        HttpResponse::build(status)
             .content_type("application/json")
-            .json(json!({ "message": self.message }));
+            .json(self.to_response())
+    }
+
+    fn render_response(&self) -> HttpResponse {
+        error!("{}", self.message());
+        self.error_response()
     }
 }
@@ -1,10 +1,27 @@
+use actix_web::http::header;
 use actix_web::{web, HttpRequest, HttpResponse};
 use futures::future::Future;
 use juniper::http::graphiql::graphiql_source;
 use juniper::http::GraphQLRequest;
+use serde::Deserialize;
+use serde_json::Map;
 
+use crate::graphql::prefetch::Prefetch;
+use crate::graphql::schema::Context;
+use crate::language::tags::parse_accept_language;
+use crate::server::admin;
 use crate::server::state::State;
 
+/// The body of a GraphQL request. It is read here rather than as juniper's `GraphQLRequest`,
+/// which keeps the query to itself, so that the query can be gone through before it is executed.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLBody {
+    pub query: String,
+    pub operation_name: Option<String>,
+    pub variables: Option<serde_json::Value>,
+}
+
 pub fn graphiql(req: HttpRequest) -> HttpResponse {
     let html = graphiql_source(&format!("http://{}/graphql", &req.connection_info().host()));
     HttpResponse::Ok()
@@ -13,17 +30,76 @@ pub fn graphiql(req: HttpRequest) -> HttpResponse {
 }
 
 pub fn graphql(
+    req: HttpRequest,
     state: web::Data<State>,
-    request: web::Json<GraphQLRequest>,
+    body: web::Json<GraphQLBody>,
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
-    web::block(move || {
-        let res = request.execute(&state.graphql_schema, &state);
-        Ok::<_, serde_json::error::Error>(serde_json::to_string(&res)?)
-    })
-    .map_err(actix_web::Error::from)
-    .and_then(|query| {
-        Ok(HttpResponse::Ok()
-            .content_type("application/json")
-            .body(query))
-    })
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let authorization = admin::authorize(&state, authorization);
+
+    let ui_languages = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(parse_accept_language)
+        .unwrap_or_default();
+
+    let body = body.into_inner();
+    let variables = match &body.variables {
+        Some(serde_json::Value::Object(variables)) => variables.clone(),
+        _ => Map::new(),
+    };
+
+    let mut prefetch = Prefetch::from_query(
+        &body.query,
+        body.operation_name.as_ref().map(String::as_str),
+        &variables,
+    );
+
+    let request = GraphQLRequest::new(
+        body.query,
+        body.operation_name,
+        body.variables
+            .and_then(|variables| serde_json::from_value(variables).ok()),
+    );
+    let state = state.get_ref().clone();
+
+    // juniper can't validate a query without executing it. A mutation is executed without its
+    // model actions having run, which only makes its fields fail, so that they are not run for
+    // a query juniper then rejects.
+    if prefetch.has_model_actions() {
+        let context = Context {
+            state: state.clone(),
+            ui_languages: vec![],
+            prefetch: Prefetch::default(),
+        };
+
+        if !request.execute(&context.graphql_schema, &context).is_ok() {
+            prefetch = Prefetch::default();
+        }
+    }
+
+    // The checks of the query run without holding a thread, and the query is then executed
+    // once on the worker, its fields taking their results from `Prefetch`
+    prefetch
+        .run(&state, authorization)
+        .from_err()
+        .and_then(move |prefetch| {
+            let context = Context {
+                state,
+                ui_languages,
+                prefetch,
+            };
+            let res = request.execute(&context.graphql_schema, &context);
+            let query = serde_json::to_string(&res)?;
+
+            Ok::<_, actix_web::Error>(
+                HttpResponse::Ok()
+                    .content_type("application/json")
+                    .body(query),
+            )
+        })
 }
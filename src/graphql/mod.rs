@@ -1,2 +1,3 @@
 pub mod handlers;
+pub mod prefetch;
 pub mod schema;
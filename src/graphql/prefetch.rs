@@ -0,0 +1,735 @@
+use std::cell::RefCell;
+
+use futures::future::{err, join_all, Future};
+use futures::stream::{self, Stream};
+use graphql_parser::query::{
+    Definition, Directive, Field, OperationDefinition, Selection, SelectionSet, Value,
+    VariableDefinition,
+};
+use hashbrown::HashMap;
+use juniper::{FromInputValue, InputValue};
+use serde_json::Map;
+
+use crate::error::ApiError;
+use crate::graphql::schema::{CheckInput, GrammarCheckOptions, ModelType, SpellerCheckOptions};
+use crate::language::batch::{self, BatchInput, BatchResult};
+use crate::language::data_files::DataFileType;
+use crate::language::grammar::{GramcheckRequest, GramcheckResponse};
+use crate::language::hyphenation::{HyphenationRequest, HyphenationResponse};
+use crate::language::speller::{SpellerRequest, SpellerResponse};
+use crate::metrics::track;
+use crate::server::admin::{self, ModelActionResponse};
+use crate::server::state::State;
+
+type Check<T> = Box<dyn Future<Item = T, Error = ApiError>>;
+type ModelActionResult = Result<ModelActionResponse, ApiError>;
+
+/// The checks of one kind a query asks for, along with their results once they have run
+struct Checks<R, T>(Vec<(R, Option<Result<T, ApiError>>)>);
+
+impl<R: PartialEq + 'static, T: Clone + 'static> Checks<R, T> {
+    fn new() -> Self {
+        Checks(vec![])
+    }
+
+    /// The same field may be selected more than once under aliases, and is only checked once
+    fn push(&mut self, request: R) {
+        if !self.0.iter().any(|(collected, _)| *collected == request) {
+            self.0.push((request, None));
+        }
+    }
+
+    fn get(&self, request: &R) -> Result<T, ApiError> {
+        match self.0.iter().find(|(collected, _)| collected == request) {
+            Some((_, Some(result))) => result.clone(),
+            _ => Err(not_prefetched()),
+        }
+    }
+
+    /// Starts every check at once, completing when all of them have
+    fn run<F>(self, mut check: F) -> Check<Self>
+    where
+        F: FnMut(&R) -> Check<T>,
+    {
+        let (requests, checks): (Vec<R>, Vec<_>) = self
+            .0
+            .into_iter()
+            .map(|(request, _)| {
+                let result = check(&request).then(|result| Ok::<_, ApiError>(Some(result)));
+                (request, result)
+            })
+            .unzip();
+
+        Box::new(
+            join_all(checks).map(|results| Checks(requests.into_iter().zip(results).collect())),
+        )
+    }
+}
+
+/// A model administration mutation of a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelActionRequest {
+    Load {
+        data_type: DataFileType,
+        language: String,
+        path: String,
+    },
+    Unload {
+        data_type: DataFileType,
+        language: String,
+    },
+    Reload {
+        data_type: DataFileType,
+        language: String,
+    },
+}
+
+impl ModelActionRequest {
+    fn run(&self, state: &State) -> Check<ModelActionResponse> {
+        match self {
+            ModelActionRequest::Load {
+                data_type,
+                language,
+                path,
+            } => admin::load_model(state, *data_type, language, path),
+            ModelActionRequest::Unload {
+                data_type,
+                language,
+            } => admin::unload_model(state, *data_type, language),
+            ModelActionRequest::Reload {
+                data_type,
+                language,
+            } => admin::reload_model(state, *data_type, language),
+        }
+    }
+}
+
+/// The checks and model actions a GraphQL query needs. juniper resolves fields synchronously,
+/// so they are found by going through the query before it is executed and are run as futures:
+/// the checks all at the same time, and the model actions one after another in the order of
+/// the query. The query is then executed once, its fields taking their results from here. No
+/// thread is held while the backends work.
+pub struct Prefetch {
+    grammar: Checks<(String, GramcheckRequest), GramcheckResponse>,
+    speller: Checks<(String, SpellerRequest), SpellerResponse>,
+    hyphenation: Checks<(String, HyphenationRequest), HyphenationResponse>,
+    batches: Checks<Vec<BatchInput>, Vec<(String, BatchResult)>>,
+    model_actions: RefCell<Vec<(ModelActionRequest, Option<ModelActionResult>)>>,
+}
+
+impl Default for Prefetch {
+    fn default() -> Self {
+        Prefetch {
+            grammar: Checks::new(),
+            speller: Checks::new(),
+            hyphenation: Checks::new(),
+            batches: Checks::new(),
+            model_actions: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl Prefetch {
+    /// Finds what the operation a request executes needs. A query that can't be made sense of
+    /// needs nothing, and is left to juniper to report on when it is executed.
+    pub fn from_query(
+        query: &str,
+        operation_name: Option<&str>,
+        variables: &Map<String, serde_json::Value>,
+    ) -> Self {
+        let mut prefetch = Prefetch::default();
+
+        let document = match graphql_parser::parse_query(query) {
+            Ok(document) => document,
+            Err(_) => return prefetch,
+        };
+
+        let mut operations = vec![];
+        let mut fragments = HashMap::new();
+
+        for definition in &document.definitions {
+            match definition {
+                Definition::Operation(operation) => operations.push(operation),
+                Definition::Fragment(fragment) => {
+                    fragments.insert(fragment.name.as_str(), &fragment.selection_set);
+                }
+            }
+        }
+
+        let operation = match operation_name {
+            Some(name) => operations
+                .into_iter()
+                .find(|operation| operation_parts(operation).1 == Some(name)),
+            None if operations.len() == 1 => operations.pop(),
+            None => None,
+        };
+
+        let (is_mutation, _, variable_definitions, selection_set) = match operation {
+            Some(operation) => operation_parts(operation),
+            None => return prefetch,
+        };
+
+        let walker = Walker::new(fragments, variables, variable_definitions);
+
+        if is_mutation {
+            // Root fields selected more than once under the same name are only resolved once
+            let mut response_keys = vec![];
+
+            for field in walker.fields(selection_set) {
+                let response_key = field.alias.as_ref().unwrap_or(&field.name);
+
+                if response_keys.contains(&response_key) {
+                    continue;
+                }
+                response_keys.push(response_key);
+
+                if let Some(request) = walker.model_action(field) {
+                    prefetch.model_actions.get_mut().push((request, None));
+                }
+            }
+
+            return prefetch;
+        }
+
+        for field in walker.fields(selection_set) {
+            match field.name.as_str() {
+                "suggestions" => prefetch.collect_suggestions(&walker, field),
+                "check" => {
+                    if let Some(inputs) = walker.argument::<Vec<CheckInput>>(field, "inputs") {
+                        prefetch
+                            .batches
+                            .push(inputs.into_iter().map(BatchInput::from).collect());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        prefetch
+    }
+
+    fn collect_suggestions<'a>(&mut self, walker: &Walker<'a>, field: &'a Field) {
+        let text = walker.argument::<String>(field, "text");
+        let language = walker.argument::<String>(field, "language");
+
+        let (text, language) = match (text, language) {
+            (Some(text), Some(language)) => (text, language),
+            _ => return,
+        };
+
+        for field in walker.fields(&field.selection_set) {
+            let arguments = walker.arguments(field);
+
+            match field.name.as_str() {
+                "grammar" => {
+                    if let Some(options) = GrammarCheckOptions::from_input_value(&arguments) {
+                        self.grammar
+                            .push((language.clone(), options.request(text.clone())));
+                    }
+                }
+                "speller" => {
+                    if let Some(options) = SpellerCheckOptions::from_input_value(&arguments) {
+                        self.speller
+                            .push((language.clone(), options.request(text.clone())));
+                    }
+                }
+                "hyphenation" => self
+                    .hyphenation
+                    .push((language.clone(), HyphenationRequest { text: text.clone() })),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the query runs any model actions, which must only happen once it is known to be
+    /// valid
+    pub fn has_model_actions(&self) -> bool {
+        !self.model_actions.borrow().is_empty()
+    }
+
+    pub fn grammar(
+        &self,
+        language: &str,
+        request: GramcheckRequest,
+    ) -> Result<GramcheckResponse, ApiError> {
+        self.grammar.get(&(language.to_owned(), request))
+    }
+
+    pub fn speller(
+        &self,
+        language: &str,
+        request: SpellerRequest,
+    ) -> Result<SpellerResponse, ApiError> {
+        self.speller.get(&(language.to_owned(), request))
+    }
+
+    pub fn hyphenation(
+        &self,
+        language: &str,
+        request: HyphenationRequest,
+    ) -> Result<HyphenationResponse, ApiError> {
+        self.hyphenation.get(&(language.to_owned(), request))
+    }
+
+    pub fn batch(&self, inputs: Vec<BatchInput>) -> Result<Vec<(String, BatchResult)>, ApiError> {
+        self.batches.get(&inputs)
+    }
+
+    /// Takes the result of a model action, as each one is resolved once
+    pub fn model_action(&self, request: ModelActionRequest) -> ModelActionResult {
+        let mut model_actions = self.model_actions.borrow_mut();
+
+        let index = model_actions
+            .iter()
+            .position(|(collected, result)| *collected == request && result.is_some())
+            .ok_or_else(not_prefetched)?;
+
+        model_actions
+            .remove(index)
+            .1
+            .unwrap_or_else(|| Err(not_prefetched()))
+    }
+
+    /// Runs the checks and model actions, returning the results to execute the query with.
+    /// Model actions are only run with the admin token, and fail with the authorization error
+    /// otherwise.
+    pub fn run(
+        self,
+        state: &State,
+        authorization: Result<(), ApiError>,
+    ) -> impl Future<Item = Self, Error = ApiError> {
+        let grammar = self
+            .grammar
+            .run(|(language, request)| grammar_check(state, request.clone(), language));
+        let speller = self
+            .speller
+            .run(|(language, request)| speller_check(state, request.clone(), language));
+        let hyphenation = self
+            .hyphenation
+            .run(|(language, request)| hyphenation_check(state, request.clone(), language));
+        let batches = self
+            .batches
+            .run(|inputs| batch::check_batch(state, inputs.clone()));
+
+        let state = state.clone();
+        let model_actions = stream::iter_ok::<_, ApiError>(self.model_actions.into_inner())
+            .and_then(move |(request, _)| {
+                let result: Check<ModelActionResponse> = match &authorization {
+                    Ok(()) => request.run(&state),
+                    Err(e) => Box::new(err(e.clone())),
+                };
+
+                result.then(move |result| Ok::<_, ApiError>((request, Some(result))))
+            })
+            .collect();
+
+        grammar
+            .join5(speller, hyphenation, batches, model_actions)
+            .map(
+                |(grammar, speller, hyphenation, batches, model_actions)| Prefetch {
+                    grammar,
+                    speller,
+                    hyphenation,
+                    batches,
+                    model_actions: RefCell::new(model_actions),
+                },
+            )
+    }
+}
+
+fn not_prefetched() -> ApiError {
+    ApiError::Internal("The field was not found in the query before executing it".to_owned())
+}
+
+/// Whether an operation is a mutation, along with its name, variables and selections.
+/// Subscriptions are treated as queries, juniper rejects them.
+fn operation_parts(
+    operation: &OperationDefinition,
+) -> (bool, Option<&str>, &[VariableDefinition], &SelectionSet) {
+    match operation {
+        OperationDefinition::SelectionSet(selection_set) => (false, None, &[], selection_set),
+        OperationDefinition::Query(query) => (
+            false,
+            query.name.as_ref().map(String::as_str),
+            &query.variable_definitions,
+            &query.selection_set,
+        ),
+        OperationDefinition::Mutation(mutation) => (
+            true,
+            mutation.name.as_ref().map(String::as_str),
+            &mutation.variable_definitions,
+            &mutation.selection_set,
+        ),
+        OperationDefinition::Subscription(subscription) => (
+            false,
+            subscription.name.as_ref().map(String::as_str),
+            &subscription.variable_definitions,
+            &subscription.selection_set,
+        ),
+    }
+}
+
+/// Goes through the selections of an operation, reading arguments the way juniper does: values
+/// are turned into juniper input values and converted with `FromInputValue`
+struct Walker<'a> {
+    fragments: HashMap<&'a str, &'a SelectionSet>,
+    variables: Map<String, serde_json::Value>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(
+        fragments: HashMap<&'a str, &'a SelectionSet>,
+        variables: &Map<String, serde_json::Value>,
+        variable_definitions: &[VariableDefinition],
+    ) -> Self {
+        let mut walker = Walker {
+            fragments,
+            variables: variables.clone(),
+        };
+
+        for definition in variable_definitions {
+            if walker.variables.contains_key(&definition.name) {
+                continue;
+            }
+
+            if let Some(default) = &definition.default_value {
+                let default = walker.json(default);
+                walker.variables.insert(definition.name.clone(), default);
+            }
+        }
+
+        walker
+    }
+
+    /// The fields of a selection set, with fragments spread and skipped fields left out
+    fn fields(&self, selection_set: &'a SelectionSet) -> Vec<&'a Field> {
+        let mut fields = vec![];
+        self.collect_fields(selection_set, &mut vec![], &mut fields);
+        fields
+    }
+
+    fn collect_fields(
+        &self,
+        selection_set: &'a SelectionSet,
+        spread: &mut Vec<&'a str>,
+        fields: &mut Vec<&'a Field>,
+    ) {
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    if self.is_included(&field.directives) {
+                        fields.push(field);
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    if self.is_included(&fragment.directives) {
+                        self.collect_fields(&fragment.selection_set, spread, fields);
+                    }
+                }
+                Selection::FragmentSpread(fragment_spread) => {
+                    let name = fragment_spread.fragment_name.as_str();
+
+                    // juniper rejects fragments spread within themselves, they are only kept
+                    // from looping here
+                    if !self.is_included(&fragment_spread.directives) || spread.contains(&name) {
+                        continue;
+                    }
+
+                    if let Some(selection_set) = self.fragments.get(name) {
+                        spread.push(name);
+                        self.collect_fields(selection_set, spread, fields);
+                        spread.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies the `@skip` and `@include` directives
+    fn is_included(&self, directives: &[Directive]) -> bool {
+        directives.iter().all(|directive| {
+            let condition = directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "if")
+                .and_then(|(_, value)| bool::from_input_value(&self.input_value(value)));
+
+            match (directive.name.as_str(), condition) {
+                ("skip", Some(condition)) => !condition,
+                ("include", Some(condition)) => condition,
+                _ => true,
+            }
+        })
+    }
+
+    fn model_action(&self, field: &Field) -> Option<ModelActionRequest> {
+        let data_type = self.argument::<ModelType>(field, "modelType")?.into();
+        let language = self.argument::<String>(field, "language")?;
+
+        match field.name.as_str() {
+            "loadModel" => Some(ModelActionRequest::Load {
+                data_type,
+                language,
+                path: self.argument(field, "path")?,
+            }),
+            "unloadModel" => Some(ModelActionRequest::Unload {
+                data_type,
+                language,
+            }),
+            "reloadModel" => Some(ModelActionRequest::Reload {
+                data_type,
+                language,
+            }),
+            _ => None,
+        }
+    }
+
+    fn argument<T: FromInputValue>(&self, field: &Field, name: &str) -> Option<T> {
+        let value = field
+            .arguments
+            .iter()
+            .find(|(argument, _)| argument == name)
+            .map(|(_, value)| self.input_value(value))
+            .unwrap_or_else(InputValue::null);
+
+        T::from_input_value(&value)
+    }
+
+    /// All arguments of a field as an input object, to be read as one of the input types that
+    /// have the same fields as the arguments
+    fn arguments(&self, field: &Field) -> InputValue {
+        let arguments = field
+            .arguments
+            .iter()
+            .map(|(name, value)| (name.clone(), self.json(value)))
+            .collect();
+
+        to_input_value(serde_json::Value::Object(arguments))
+    }
+
+    fn input_value(&self, value: &Value) -> InputValue {
+        match value {
+            Value::Enum(name) => InputValue::enum_value(name),
+            Value::List(values) => {
+                InputValue::list(values.iter().map(|value| self.input_value(value)).collect())
+            }
+            value => to_input_value(self.json(value)),
+        }
+    }
+
+    /// Converts a value of the query to JSON, which variables are given as, with its
+    /// variables substituted. Enum values become strings, which juniper accepts for enums as
+    /// it does in variables.
+    fn json(&self, value: &Value) -> serde_json::Value {
+        match value {
+            Value::Variable(name) => self
+                .variables
+                .get(name)
+                .cloned()
+                .unwrap_or(serde_json::Value::Null),
+            Value::Int(number) => number
+                .as_i64()
+                .map(serde_json::Value::from)
+                .unwrap_or(serde_json::Value::Null),
+            Value::Float(number) => serde_json::Value::from(*number),
+            Value::String(string) => serde_json::Value::from(string.as_str()),
+            Value::Boolean(boolean) => serde_json::Value::from(*boolean),
+            Value::Null => serde_json::Value::Null,
+            Value::Enum(name) => serde_json::Value::from(name.as_str()),
+            Value::List(values) => {
+                serde_json::Value::Array(values.iter().map(|value| self.json(value)).collect())
+            }
+            Value::Object(fields) => serde_json::Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.json(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn to_input_value(value: serde_json::Value) -> InputValue {
+    serde_json::from_value(value).unwrap_or_else(|_| InputValue::null())
+}
+
+fn grammar_check(
+    state: &State,
+    request: GramcheckRequest,
+    language: &str,
+) -> Check<GramcheckResponse> {
+    let grammar_suggestions = &state.language_functions.grammar_suggestions;
+    let model_key = grammar_suggestions.model_key(&request, language);
+
+    Box::new(track(
+        "graphql_grammar",
+        model_key,
+        grammar_suggestions.suggestions(request, language),
+    ))
+}
+
+fn speller_check(state: &State, request: SpellerRequest, language: &str) -> Check<SpellerResponse> {
+    let spelling_suggestions = &state.language_functions.spelling_suggestions;
+    let model_key = spelling_suggestions.model_key(&request, language);
+
+    Box::new(track(
+        "graphql_speller",
+        model_key,
+        spelling_suggestions.suggestions(request, language),
+    ))
+}
+
+fn hyphenation_check(
+    state: &State,
+    request: HyphenationRequest,
+    language: &str,
+) -> Check<HyphenationResponse> {
+    let hyphenation_suggestions = &state.language_functions.hyphenation_suggestions;
+    let model_key = hyphenation_suggestions.model_key(&request, language);
+
+    Box::new(track(
+        "graphql_hyphenation",
+        model_key,
+        hyphenation_suggestions.suggestions(request, language),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::speller::SpellerOptions;
+    use serde_json::json;
+
+    fn prefetch(
+        query: &str,
+        operation_name: Option<&str>,
+        variables: serde_json::Value,
+    ) -> Prefetch {
+        let variables = match variables {
+            serde_json::Value::Object(variables) => variables,
+            _ => Map::new(),
+        };
+
+        Prefetch::from_query(query, operation_name, &variables)
+    }
+
+    fn requests<R: Clone, T>(checks: &Checks<R, T>) -> Vec<R> {
+        checks
+            .0
+            .iter()
+            .map(|(request, _)| request.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let prefetch = prefetch(
+            r#"query Check($text: String!, $language: String = "se", $max: Int) {
+                suggestions(text: $text, language: $language) {
+                    ...Speller
+                    all: speller { language }
+                    again: speller { language }
+                    hyphenation @skip(if: true) { language }
+                }
+            }
+
+            fragment Speller on Suggestions {
+                best: speller(maxSuggestions: $max, recase: false) { language }
+                ... on Suggestions { grammar(ignoreTags: "typo") { language } }
+            }"#,
+            None,
+            json!({ "text": "pákhat", "max": 1 }),
+        );
+
+        let best = SpellerRequest {
+            options: SpellerOptions {
+                max_suggestions: Some(1),
+                recase: Some(false),
+                ..SpellerOptions::default()
+            },
+            ..SpellerRequest::new("pákhat".to_owned())
+        };
+        let all = SpellerRequest::new("pákhat".to_owned());
+        assert_eq!(
+            requests(&prefetch.speller),
+            vec![("se".to_owned(), best), ("se".to_owned(), all)]
+        );
+
+        let grammar = GramcheckRequest {
+            ignore_tags: vec!["typo".to_owned()],
+            ..GramcheckRequest::new("pákhat".to_owned())
+        };
+        assert_eq!(
+            requests(&prefetch.grammar),
+            vec![("se".to_owned(), grammar)]
+        );
+
+        assert!(requests(&prefetch.hyphenation).is_empty());
+        assert!(!prefetch.has_model_actions());
+    }
+
+    #[test]
+    fn test_operation_name() {
+        let query = r#"
+            query Speller { suggestions(text: "a", language: "se") { speller { language } } }
+            query Hyphenation { suggestions(text: "b", language: "se") { hyphenation { language } } }
+        "#;
+
+        let named = prefetch(query, Some("Hyphenation"), json!(null));
+        assert!(requests(&named.speller).is_empty());
+        assert_eq!(
+            requests(&named.hyphenation),
+            vec![(
+                "se".to_owned(),
+                HyphenationRequest {
+                    text: "b".to_owned()
+                }
+            )]
+        );
+
+        let unnamed = prefetch(query, None, json!(null));
+        assert!(requests(&unnamed.speller).is_empty());
+        assert!(requests(&unnamed.hyphenation).is_empty());
+
+        let invalid = prefetch("query { suggestions(", None, json!(null));
+        assert!(requests(&invalid.speller).is_empty());
+    }
+
+    #[test]
+    fn test_model_actions() {
+        let prefetch = prefetch(
+            r#"mutation {
+                first: reloadModel(modelType: SPELLING, language: "se") { action }
+                first: reloadModel(modelType: SPELLING, language: "se") { language }
+                second: reloadModel(modelType: SPELLING, language: "se") { action }
+                loadModel(modelType: HYPHENATION, language: "xx", path: "se.hfstol") { action }
+                unloadModel(modelType: DICTIONARY, language: "se") { action }
+            }"#,
+            None,
+            json!(null),
+        );
+
+        let reload = ModelActionRequest::Reload {
+            data_type: DataFileType::Spelling,
+            language: "se".to_owned(),
+        };
+        let load = ModelActionRequest::Load {
+            data_type: DataFileType::Hyphenation,
+            language: "xx".to_owned(),
+            path: "se.hfstol".to_owned(),
+        };
+
+        let model_actions: Vec<_> = prefetch
+            .model_actions
+            .borrow()
+            .iter()
+            .map(|(request, _)| request.clone())
+            .collect();
+        assert_eq!(model_actions, vec![reload.clone(), reload.clone(), load]);
+
+        match prefetch.model_action(reload) {
+            Err(ApiError::Internal(_)) => {}
+            _ => panic!("A model action that has not run has no result"),
+        }
+    }
+}
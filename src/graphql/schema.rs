@@ -1,15 +1,46 @@
-use futures::future::Future;
-use juniper::{graphql_object, EmptyMutation, FieldResult, GraphQLObject, RootNode};
+use std::ops::Deref;
 
-use crate::language::grammar::{self, GramcheckRequest};
-use crate::language::hyphenation::{self, HyphenationRequest};
-use crate::language::speller::{self, SpellerRequest};
-use crate::server::state::InnerState;
+use juniper::{
+    graphql_object, graphql_value, FieldError, FieldResult, GraphQLEnum, GraphQLInputObject,
+    GraphQLObject, RootNode,
+};
+
+use crate::error::ApiError;
+use crate::graphql::prefetch::{ModelActionRequest, Prefetch};
+use crate::language::batch::{
+    BatchGrammarOptions, BatchInput, BatchOptions, BatchResult, BatchSpellerOptions,
+};
+use crate::language::data_files::{self, DataFileType};
+use crate::language::grammar::{
+    self, GramcheckPreferencesResponse, GramcheckRequest, GramcheckResponse,
+};
+use crate::language::hyphenation::{self, HyphenationRequest, HyphenationResponse};
+use crate::language::models::{self, ModelStatus};
+use crate::language::speller::{self, SpellerOptions, SpellerRequest, SpellerResponse};
+use crate::language::tags::parse_accept_language;
+use crate::language::text;
+use crate::server::admin::ModelActionResponse;
+use crate::server::state::{InnerState, State};
 use divvunspell::speller::suggestion::Suggestion;
 
-impl juniper::Context for InnerState {}
+/// Per-request context, carrying the languages of its `Accept-Language` header and the results
+/// of the checks and model actions of the query
+pub struct Context {
+    pub state: State,
+    pub ui_languages: Vec<String>,
+    pub prefetch: Prefetch,
+}
+
+impl juniper::Context for Context {}
+
+impl Deref for Context {
+    type Target = InnerState;
+
+    fn deref(&self) -> &InnerState {
+        &self.state
+    }
+}
 
-#[derive(Debug)]
 pub struct Suggestions {
     text: String,
     language: String,
@@ -17,17 +48,48 @@ pub struct Suggestions {
 
 #[derive(GraphQLObject)]
 pub struct Grammar {
+    #[graphql(description = "Tag of the model that handled the request")]
+    pub language: String,
     pub errs: Vec<GramcheckErrResponse>,
 }
 
 #[derive(GraphQLObject)]
 pub struct Speller {
+    #[graphql(description = "Tag of the model that handled the request")]
+    pub language: String,
     pub results: Vec<SpellerResult>,
 }
 
+impl From<GramcheckResponse> for Grammar {
+    fn from(item: GramcheckResponse) -> Self {
+        Grammar {
+            language: item.language,
+            errs: item
+                .errs
+                .into_iter()
+                .map(|response| GramcheckErrResponse::from(response))
+                .collect(),
+        }
+    }
+}
+
+impl From<SpellerResponse> for Speller {
+    fn from(item: SpellerResponse) -> Self {
+        Speller {
+            language: item.language,
+            results: item
+                .results
+                .into_iter()
+                .map(|suggestion| SpellerResult::from(suggestion))
+                .collect(),
+        }
+    }
+}
+
 #[derive(GraphQLObject)]
 pub struct SpellerResult {
     pub word: String,
+    pub offsets: TextOffsets,
     pub is_correct: bool,
     pub suggestions: Vec<SpellerSuggestion>,
 }
@@ -36,6 +98,7 @@ impl From<speller::SpellerResult> for SpellerResult {
     fn from(item: speller::SpellerResult) -> Self {
         SpellerResult {
             word: item.word,
+            offsets: TextOffsets::from(item.offsets),
             is_correct: item.is_correct,
             suggestions: item
                 .suggestions
@@ -46,6 +109,39 @@ impl From<speller::SpellerResult> for SpellerResult {
     }
 }
 
+#[derive(GraphQLObject)]
+pub struct Span {
+    pub start: i32,
+    pub end: i32,
+}
+
+impl From<text::Span> for Span {
+    fn from(item: text::Span) -> Self {
+        Span {
+            start: item.start as i32,
+            end: item.end as i32,
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "Position of a word in bytes, Unicode scalar values and UTF-16 code units")]
+pub struct TextOffsets {
+    pub bytes: Span,
+    pub chars: Span,
+    pub utf16: Span,
+}
+
+impl From<text::TextOffsets> for TextOffsets {
+    fn from(item: text::TextOffsets) -> Self {
+        TextOffsets {
+            bytes: Span::from(item.bytes),
+            chars: Span::from(item.chars),
+            utf16: Span::from(item.utf16),
+        }
+    }
+}
+
 #[derive(GraphQLObject)]
 pub struct SpellerSuggestion {
     pub value: String,
@@ -89,12 +185,28 @@ impl From<grammar::GramcheckErrResponse> for GramcheckErrResponse {
 
 #[derive(GraphQLObject)]
 pub struct Hyphenation {
+    #[graphql(description = "Tag of the model that handled the request")]
+    pub language: String,
     pub results: Vec<HyphenationResult>,
 }
 
+impl From<HyphenationResponse> for Hyphenation {
+    fn from(item: HyphenationResponse) -> Self {
+        Hyphenation {
+            language: item.language,
+            results: item
+                .results
+                .into_iter()
+                .map(|result| HyphenationResult::from(result))
+                .collect(),
+        }
+    }
+}
+
 #[derive(GraphQLObject)]
 pub struct HyphenationResult {
     pub word: String,
+    pub offsets: TextOffsets,
     pub hyphenations: Vec<HyphenationPattern>,
 }
 
@@ -102,6 +214,7 @@ impl From<hyphenation::HyphenationResult> for HyphenationResult {
     fn from(item: hyphenation::HyphenationResult) -> Self {
         HyphenationResult {
             word: item.word,
+            offsets: TextOffsets::from(item.offsets),
             hyphenations: item
                 .hyphenations
                 .into_iter()
@@ -126,108 +239,657 @@ impl From<hyphenation::HyphenationPattern> for HyphenationPattern {
     }
 }
 
+#[derive(GraphQLEnum, Clone, Copy)]
+pub enum ModelType {
+    Spelling,
+    Grammar,
+    Hyphenation,
+}
+
+impl From<ModelType> for DataFileType {
+    fn from(item: ModelType) -> Self {
+        match item {
+            ModelType::Spelling => DataFileType::Spelling,
+            ModelType::Grammar => DataFileType::Grammar,
+            ModelType::Hyphenation => DataFileType::Hyphenation,
+        }
+    }
+}
+
+impl From<DataFileType> for ModelType {
+    fn from(item: DataFileType) -> Self {
+        match item {
+            DataFileType::Spelling => ModelType::Spelling,
+            DataFileType::Grammar => ModelType::Grammar,
+            DataFileType::Hyphenation => ModelType::Hyphenation,
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "A loaded language model")]
+pub struct Model {
+    pub language: String,
+    pub path: String,
+    pub size: f64,
+    #[graphql(description = "Modification time of the file in seconds since the Unix epoch")]
+    pub modified: Option<f64>,
+    #[graphql(description = "Time the model was loaded in seconds since the Unix epoch")]
+    pub loaded_at: f64,
+    pub restarts: i32,
+    pub alive: bool,
+    pub archive: Option<ArchiveInfo>,
+}
+
+impl From<ModelStatus> for Model {
+    fn from(item: ModelStatus) -> Self {
+        Model {
+            language: item.language,
+            path: item.path,
+            size: item.size as f64,
+            modified: item.modified.map(|m| m as f64),
+            loaded_at: item.loaded_at as f64,
+            restarts: item.restarts as i32,
+            alive: item.alive,
+            archive: item.archive.map(ArchiveInfo::from),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "The loaded models of each type")]
+pub struct Models {
+    pub speller: Vec<Model>,
+    pub grammar: Vec<Model>,
+    pub hyphenation: Vec<Model>,
+}
+
+impl Models {
+    fn new(state: &InnerState) -> Self {
+        let models = |data_type| -> Vec<Model> {
+            state
+                .models(data_type)
+                .into_iter()
+                .map(Model::from)
+                .collect()
+        };
+
+        Models {
+            speller: models(DataFileType::Spelling),
+            grammar: models(DataFileType::Grammar),
+            hyphenation: models(DataFileType::Hyphenation),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "A language with models loaded")]
+pub struct Language {
+    pub tag: String,
+    pub tag1: Option<String>,
+    pub tag3: Option<String>,
+    #[graphql(description = "English name of the language")]
+    pub name: Option<String>,
+    #[graphql(description = "Name of the language in the language itself")]
+    pub autonym: Option<String>,
+    #[graphql(description = "Name of the language in the first UI language it is known in")]
+    pub localized_name: Option<String>,
+    pub source: Option<String>,
+    #[graphql(description = "Types of the models loaded for the language")]
+    pub capabilities: Vec<ModelType>,
+    pub models: Vec<LanguageModel>,
+}
+
+impl From<data_files::LanguageInfo> for Language {
+    fn from(item: data_files::LanguageInfo) -> Self {
+        Language {
+            tag: item.tag,
+            tag1: item.tag1.map(str::to_owned),
+            tag3: item.tag3.map(str::to_owned),
+            name: item.name.map(str::to_owned),
+            autonym: item.autonym.map(str::to_owned),
+            localized_name: item.localized_name.map(str::to_owned),
+            source: item.source.map(str::to_owned),
+            capabilities: item
+                .capabilities
+                .into_iter()
+                .filter_map(DataFileType::from_dir)
+                .map(ModelType::from)
+                .collect(),
+            models: item
+                .models
+                .into_iter()
+                .filter_map(LanguageModel::new)
+                .collect(),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct LanguageModel {
+    pub model_type: ModelType,
+    #[graphql(description = "Tag the model was loaded for, including its variant")]
+    pub tag: String,
+    pub variant: Option<String>,
+    pub size: f64,
+    #[graphql(description = "Modification time of the file in seconds since the Unix epoch")]
+    pub modified: Option<f64>,
+    #[graphql(description = "Title of the speller archive in the first UI language it has one in")]
+    pub title: Option<String>,
+    pub archive: Option<ArchiveInfo>,
+}
+
+impl LanguageModel {
+    fn new(item: data_files::LanguageModel) -> Option<Self> {
+        Some(LanguageModel {
+            model_type: DataFileType::from_dir(item.data_type)?.into(),
+            tag: item.tag,
+            variant: item.variant,
+            size: item.size as f64,
+            modified: item.modified.map(|m| m as f64),
+            title: item.title,
+            archive: item.archive.map(ArchiveInfo::from),
+        })
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct GrammarPreferences {
+    #[graphql(description = "Tag of the grammar checker the request resolved to")]
+    pub language: String,
+    pub error_tags: Vec<ErrorTag>,
+}
+
+impl From<GramcheckPreferencesResponse> for GrammarPreferences {
+    fn from(item: GramcheckPreferencesResponse) -> Self {
+        GrammarPreferences {
+            language: item.language,
+            error_tags: item
+                .error_tags
+                .into_iter()
+                .map(|(tag, description)| ErrorTag { tag, description })
+                .collect(),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "An error tag that can be passed to `ignoreTags` or `enableTags`")]
+pub struct ErrorTag {
+    pub tag: String,
+    pub description: String,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "A loaded speller and the metadata of the archive it was loaded from")]
+pub struct SpellerInfo {
+    #[graphql(description = "Tag of the model the request resolved to")]
+    pub language: String,
+    pub variant: Option<String>,
+    pub size: f64,
+    #[graphql(description = "Modification time of the file in seconds since the Unix epoch")]
+    pub modified: Option<f64>,
+    #[graphql(description = "Time the model was loaded in seconds since the Unix epoch")]
+    pub loaded_at: f64,
+    pub archive: Option<ArchiveInfo>,
+}
+
+impl From<speller::SpellerInfo> for SpellerInfo {
+    fn from(item: speller::SpellerInfo) -> Self {
+        SpellerInfo {
+            language: item.language,
+            variant: item.variant,
+            size: item.size as f64,
+            modified: item.modified.map(|m| m as f64),
+            loaded_at: item.loaded_at as f64,
+            archive: item.archive.map(ArchiveInfo::from),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "The metadata of a speller archive, from its index.xml")]
+pub struct ArchiveInfo {
+    pub locale: String,
+    pub titles: Vec<ArchiveTitle>,
+    pub description: String,
+    pub producer: String,
+    pub version: Option<String>,
+    pub acceptor: ArchiveTransducer,
+    pub errmodel: ArchiveTransducer,
+}
+
+impl From<models::ArchiveInfo> for ArchiveInfo {
+    fn from(item: models::ArchiveInfo) -> Self {
+        ArchiveInfo {
+            locale: item.locale,
+            titles: item
+                .titles
+                .into_iter()
+                .map(|title| ArchiveTitle {
+                    lang: title.lang,
+                    value: title.value,
+                })
+                .collect(),
+            description: item.description,
+            producer: item.producer,
+            version: item.version,
+            acceptor: ArchiveTransducer::from(item.acceptor),
+            errmodel: ArchiveTransducer::from(item.errmodel),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct ArchiveTitle {
+    pub lang: Option<String>,
+    pub value: String,
+}
+
+#[derive(GraphQLObject)]
+pub struct ArchiveTransducer {
+    #[graphql(description = "Name of the transducer file in the archive")]
+    pub id: String,
+    #[graphql(description = "Uncompressed size of the transducer in bytes")]
+    pub size: Option<f64>,
+}
+
+impl From<models::ArchiveTransducer> for ArchiveTransducer {
+    fn from(item: models::ArchiveTransducer) -> Self {
+        ArchiveTransducer {
+            id: item.id,
+            size: item.size.map(|s| s as f64),
+        }
+    }
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "A text to check as part of a batch")]
+pub struct CheckInput {
+    #[graphql(
+        description = "Identifies the input in the results, must be unique within the batch"
+    )]
+    pub id: String,
+    pub text: String,
+    pub language: String,
+    #[graphql(description = "Checks to run on the text. Only the speller is run when left out.")]
+    pub options: Option<CheckOptions>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "Each check that is given is run, with its own options")]
+pub struct CheckOptions {
+    pub speller: Option<SpellerCheckOptions>,
+    pub grammar: Option<GrammarCheckOptions>,
+    pub hyphenation: Option<bool>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "The arguments of the `speller` field of `suggestions`")]
+pub struct SpellerCheckOptions {
+    pub suggest_correct: Option<bool>,
+    pub include_correct: Option<bool>,
+    pub variant: Option<String>,
+    pub max_suggestions: Option<i32>,
+    pub max_weight: Option<f64>,
+    pub beam: Option<f64>,
+    pub recase: Option<bool>,
+    pub timeout_ms: Option<i32>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "The arguments of the `grammar` field of `suggestions`")]
+pub struct GrammarCheckOptions {
+    pub ignore_tags: Option<Vec<String>>,
+    pub enable_tags: Option<Vec<String>>,
+}
+
+impl GrammarCheckOptions {
+    /// The request the `grammar` field of `suggestions` makes for `text`
+    pub fn request(self, text: String) -> GramcheckRequest {
+        GramcheckRequest {
+            ignore_tags: self.ignore_tags.unwrap_or_default(),
+            enable_tags: self.enable_tags.unwrap_or_default(),
+            ..GramcheckRequest::new(text)
+        }
+    }
+}
+
+impl SpellerCheckOptions {
+    /// The request the `speller` field of `suggestions` makes for `text`, with the defaults of
+    /// its arguments
+    pub fn request(self, text: String) -> SpellerRequest {
+        SpellerRequest {
+            suggest_correct: self.suggest_correct.unwrap_or(true),
+            include_correct: self.include_correct.unwrap_or(true),
+            variant: self.variant,
+            options: speller_options(
+                self.max_suggestions,
+                self.max_weight,
+                self.beam,
+                self.recase,
+                self.timeout_ms,
+            ),
+            ..SpellerRequest::new(text)
+        }
+    }
+}
+
+impl From<CheckInput> for BatchInput {
+    fn from(item: CheckInput) -> Self {
+        BatchInput {
+            id: item.id,
+            text: item.text,
+            language: item.language,
+            options: item.options.map(|options| BatchOptions {
+                speller: options.speller.map(|speller| BatchSpellerOptions {
+                    suggest_correct: speller.suggest_correct,
+                    include_correct: speller.include_correct,
+                    variant: speller.variant,
+                    options: speller_options(
+                        speller.max_suggestions,
+                        speller.max_weight,
+                        speller.beam,
+                        speller.recase,
+                        speller.timeout_ms,
+                    ),
+                }),
+                grammar: options.grammar.map(|grammar| BatchGrammarOptions {
+                    ignore_tags: grammar.ignore_tags.unwrap_or_default(),
+                    enable_tags: grammar.enable_tags.unwrap_or_default(),
+                }),
+                hyphenation: options.hyphenation.unwrap_or(false),
+            }),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "The results of the checks run on one input of a batch")]
+pub struct CheckResult {
+    pub id: String,
+    pub speller: Option<Speller>,
+    pub grammar: Option<Grammar>,
+    pub hyphenation: Option<Hyphenation>,
+    #[graphql(description = "Errors of the checks that failed, which leave their results empty")]
+    pub errors: Vec<CheckError>,
+}
+
+impl CheckResult {
+    fn new(id: String, result: BatchResult) -> Self {
+        CheckResult {
+            id,
+            speller: result.speller.map(Speller::from),
+            grammar: result.grammar.map(Grammar::from),
+            hyphenation: result.hyphenation.map(Hyphenation::from),
+            errors: result
+                .errors
+                .into_iter()
+                .map(|(check, error)| CheckError {
+                    check: check.to_owned(),
+                    code: error.code,
+                    message: error.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+pub struct CheckError {
+    #[graphql(description = "The check that failed: `speller`, `grammar` or `hyphenation`")]
+    pub check: String,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(GraphQLObject)]
+pub struct ModelAction {
+    pub action: String,
+    pub model_type: ModelType,
+    pub language: String,
+    #[graphql(description = "Status of the model after the action, absent once it is unloaded")]
+    pub model: Option<Model>,
+}
+
+impl ModelAction {
+    fn new(model_type: ModelType, response: ModelActionResponse) -> Self {
+        ModelAction {
+            action: response.action.to_owned(),
+            model_type,
+            language: response.language,
+            model: response.model.map(Model::from),
+        }
+    }
+}
+
 pub struct QueryRoot;
 
-graphql_object!(QueryRoot: InnerState |&self| {
-    field suggestions(&executor, text: String, language: String) -> FieldResult<Suggestions> {
-        Ok(Suggestions { text, language })
+graphql_object!(QueryRoot: Context |&self| {
+    field suggestions(text: String, language: String) -> Suggestions {
+        Suggestions { text, language }
+    }
+
+    field languages(
+        &executor,
+        ui: Option<String> as "Languages to localize names to, in the format of an Accept-Language header. Defaults to the header of the request."
+    ) -> Vec<Language> {
+        let context = executor.context();
+        let ui_languages = match ui {
+            Some(ui) => parse_accept_language(&ui),
+            None => context.ui_languages.clone(),
+        };
+
+        let models = vec![
+            (DataFileType::Grammar, context.models(DataFileType::Grammar)),
+            (DataFileType::Spelling, context.models(DataFileType::Spelling)),
+            (DataFileType::Hyphenation, context.models(DataFileType::Hyphenation)),
+        ];
+
+        data_files::languages(models, &ui_languages)
+            .into_iter()
+            .map(Language::from)
+            .collect()
+    }
+
+    field grammar_preferences(&executor, language: String) -> FieldResult<GrammarPreferences> {
+        let preferences = executor.context().gramcheck_preferences.read();
+
+        grammar::preferences(&preferences, &language)
+            .map(GrammarPreferences::from)
+            .map_err(field_error)
+    }
+
+    field models(&executor) -> Models {
+        Models::new(executor.context())
+    }
+
+    field check(
+        &executor,
+        inputs: Vec<CheckInput> as "Texts to check. Each result carries the `id` of its input, which results are to be matched by."
+    ) -> FieldResult<Vec<CheckResult>> {
+        let inputs = inputs.into_iter().map(BatchInput::from).collect();
+
+        executor
+            .context()
+            .prefetch
+            .batch(inputs)
+            .map(|results| {
+                results
+                    .into_iter()
+                    .map(|(id, result)| CheckResult::new(id, result))
+                    .collect()
+            })
+            .map_err(field_error)
+    }
+
+    field speller_info(
+        &executor,
+        language: String,
+        variant: Option<String> as "Speller variant, such as a regional orthography or model version"
+    ) -> FieldResult<SpellerInfo> {
+        let models = executor.context().models(DataFileType::Spelling);
+
+        speller::speller_info(models, &language, variant.as_ref().map(String::as_str))
+            .map(SpellerInfo::from)
+            .map_err(field_error)
     }
 });
 
-graphql_object!(Suggestions: InnerState |&self| {
+graphql_object!(Suggestions: Context |&self| {
     description: "Text suggestions"
 
-    field grammar(&executor) -> FieldResult<Grammar> {
-        get_grammar_suggestions(executor.context(), &self.text, &self.language)
+    field grammar(
+        &executor,
+        ignore_tags: Option<Vec<String>> as "Error tags to leave out of the results",
+        enable_tags: Option<Vec<String>> as "When given, only errors with these tags are returned"
+    ) -> FieldResult<Grammar> {
+        let options = GrammarCheckOptions {
+            ignore_tags,
+            enable_tags,
+        };
+        let request = options.request(self.text.to_owned());
+
+        executor
+            .context()
+            .prefetch
+            .grammar(&self.language, request)
+            .map(Grammar::from)
+            .map_err(field_error)
     }
 
-    field speller(&executor) -> FieldResult<Speller> {
-        get_speller_suggestions(executor.context(), &self.text, &self.language)
+    field speller(
+        &executor,
+        suggest_correct = true: bool as "Generate suggestions for correctly spelled words",
+        include_correct = true: bool as "Include correctly spelled words in the results",
+        variant: Option<String> as "Speller variant, such as a regional orthography or model version",
+        max_suggestions: Option<i32> as "Maximum number of suggestions per word",
+        max_weight: Option<f64> as "Discard suggestions weighted above this value",
+        beam: Option<f64> as "Discard suggestions weighted further than this from the best one",
+        recase: Option<bool> as "Retry lookups with the case of the word altered",
+        timeout_ms: Option<i32> as "Best-effort time budget in milliseconds for generating suggestions, checked between words"
+    ) -> FieldResult<Speller> {
+        let options = SpellerCheckOptions {
+            suggest_correct: Some(suggest_correct),
+            include_correct: Some(include_correct),
+            variant,
+            max_suggestions,
+            max_weight,
+            beam,
+            recase,
+            timeout_ms,
+        };
+        let request = options.request(self.text.to_owned());
+
+        executor
+            .context()
+            .prefetch
+            .speller(&self.language, request)
+            .map(Speller::from)
+            .map_err(field_error)
     }
 
     field hyphenation(&executor) -> FieldResult<Hyphenation> {
-        get_hyphenation_suggestions(executor.context(), &self.text, &self.language)
+        let request = HyphenationRequest {
+            text: self.text.to_owned(),
+        };
+
+        executor
+            .context()
+            .prefetch
+            .hyphenation(&self.language, request)
+            .map(Hyphenation::from)
+            .map_err(field_error)
     }
 });
 
-fn get_grammar_suggestions(state: &InnerState, text: &str, language: &str) -> FieldResult<Grammar> {
-    let grammar_suggestions = state
-        .language_functions
-        .grammar_suggestions
-        .suggestions(
-            GramcheckRequest {
-                text: text.to_owned(),
-            },
+pub struct MutationRoot;
+
+graphql_object!(MutationRoot: Context |&self| {
+    description: "Model administration, requires the admin token as a bearer token"
+
+    field load_model(
+        &executor,
+        model_type: ModelType,
+        language: String,
+        path: String as "Path of the model file on the server"
+    ) -> FieldResult<ModelAction> {
+        let request = ModelActionRequest::Load {
+            data_type: model_type.into(),
             language,
-        )
-        .wait();
+            path,
+        };
 
-    match grammar_suggestions {
-        Ok(gram_output) => Ok(Grammar {
-            errs: gram_output
-                .errs
-                .into_iter()
-                .map(|response| GramcheckErrResponse::from(response))
-                .collect(),
-        }),
-        Err(error) => Err(error)?,
+        executor
+            .context()
+            .prefetch
+            .model_action(request)
+            .map(|response| ModelAction::new(model_type, response))
+            .map_err(field_error)
     }
-}
 
-fn get_speller_suggestions(state: &InnerState, text: &str, language: &str) -> FieldResult<Speller> {
-    let speller_suggestions = state
-        .language_functions
-        .spelling_suggestions
-        .suggestions(
-            SpellerRequest {
-                text: text.to_owned(),
-            },
+    field unload_model(
+        &executor,
+        model_type: ModelType,
+        language: String
+    ) -> FieldResult<ModelAction> {
+        let request = ModelActionRequest::Unload {
+            data_type: model_type.into(),
             language,
-        )
-        .wait();
+        };
 
-    match speller_suggestions {
-        Ok(speller_output) => Ok(Speller {
-            results: speller_output
-                .results
-                .into_iter()
-                .map(|suggestion| SpellerResult::from(suggestion))
-                .collect(),
-        }),
-        Err(error) => Err(error)?,
-    }
-}
-
-fn get_hyphenation_suggestions(
-    state: &InnerState,
-    text: &str,
-    language: &str,
-) -> FieldResult<Hyphenation> {
-    let hyphenation_suggestions = state
-        .language_functions
-        .hyphenation_suggestions
-        .suggestions(
-            HyphenationRequest {
-                text: text.to_owned(),
-            },
+        executor
+            .context()
+            .prefetch
+            .model_action(request)
+            .map(|response| ModelAction::new(model_type, response))
+            .map_err(field_error)
+    }
+
+    field reload_model(
+        &executor,
+        model_type: ModelType,
+        language: String
+    ) -> FieldResult<ModelAction> {
+        let request = ModelActionRequest::Reload {
+            data_type: model_type.into(),
             language,
-        )
-        .wait();
+        };
 
-    match hyphenation_suggestions {
-        Ok(hyphenation_response) => Ok(Hyphenation {
-            results: hyphenation_response
-                .results
-                .into_iter()
-                .map(|result| HyphenationResult::from(result))
-                .collect(),
-        }),
-        Err(error) => Err(error)?,
+        executor
+            .context()
+            .prefetch
+            .model_action(request)
+            .map(|response| ModelAction::new(model_type, response))
+            .map_err(field_error)
+    }
+});
+
+/// Converts speller options from the types GraphQL has
+fn speller_options(
+    max_suggestions: Option<i32>,
+    max_weight: Option<f64>,
+    beam: Option<f64>,
+    recase: Option<bool>,
+    timeout_ms: Option<i32>,
+) -> SpellerOptions {
+    SpellerOptions {
+        max_suggestions: max_suggestions.map(|n| n.max(0) as usize),
+        max_weight: max_weight.map(|w| w as f32),
+        beam: beam.map(|b| b as f32),
+        recase,
+        timeout_ms: timeout_ms.map(|ms| ms.max(0) as u64),
     }
 }
 
-pub type Schema = RootNode<'static, QueryRoot, EmptyMutation<InnerState>>;
+/// Carries the `ApiError` code as an extension, matching the `code` of REST error responses
+fn field_error(error: ApiError) -> FieldError {
+    let code = error.code();
+    FieldError::new(error.message(), graphql_value!({ "code": code }))
+}
+
+pub type Schema = RootNode<'static, QueryRoot, MutationRoot>;
 
 pub fn create_schema() -> Schema {
-    Schema::new(QueryRoot {}, EmptyMutation::new())
+    Schema::new(QueryRoot {}, MutationRoot {})
 }
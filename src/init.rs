@@ -1,11 +1,11 @@
-use std::{env, fs};
+use std::{env, fs, io};
 
 use actix::{Addr, SystemRunner};
 use actix_web::dev::Server;
 use clap::{crate_version, App as ClapApp, Arg, ArgMatches};
 use log::info;
 
-use crate::config::{Config, TomlConfig};
+use crate::config::{Config, ConfigError, TomlConfig};
 use crate::server::start_server;
 use crate::server::state::create_state;
 use crate::watcher::{Start, Watcher};
@@ -16,7 +16,8 @@ pub struct App {
     pub watcher: Addr<Watcher>,
 }
 
-pub fn init_config() -> TomlConfig {
+/// Reads the config file and `DIVVUN_API_*` environment overrides into a validated config
+pub fn init_config() -> Result<Config, ConfigError> {
     let matches = ClapApp::new("divvun-api")
         .version(crate_version!())
         .arg(
@@ -57,32 +58,38 @@ pub fn init_system(config: &Config) -> (App, SystemRunner) {
     )
 }
 
-fn get_config(matches: &ArgMatches<'_>) -> TomlConfig {
+fn get_config(matches: &ArgMatches<'_>) -> Result<Config, ConfigError> {
     let default_path = "config.toml";
     let divvun_env_var = "DIVVUN_API_CONFIG_PATH";
 
-    let config_file = match env::var(divvun_env_var) {
+    let (config_file, explicit) = match env::var(divvun_env_var) {
         Ok(file) => {
             info!("Using {} from env var {} as config", file, divvun_env_var);
-            file
+            (file, true)
         }
         Err(_) => match matches.value_of("config") {
             Some(file) => {
                 info!("Using {} supplied by the CLI as config", file);
-                file
+                (file.to_owned(), true)
             }
             None => {
                 info!("Using the default {}", default_path);
-                default_path
+                (default_path.to_owned(), false)
             }
+        },
+    };
+
+    let mut toml_config = match fs::read_to_string(&config_file) {
+        Ok(config) => TomlConfig::from_toml_str(&config, &config_file)?,
+        // Without a config file, everything has to come from the environment
+        Err(ref e) if !explicit && e.kind() == io::ErrorKind::NotFound => {
+            info!("{} not found, using the environment only", config_file);
+            TomlConfig::default()
         }
-        .to_owned(),
+        Err(e) => return Err(ConfigError::Read(config_file, e)),
     };
 
-    let config =
-        fs::read_to_string(&config_file).expect(&format!("Failed to open {}", config_file));
-    let config: TomlConfig =
-        toml::from_str(&config).expect(&format!("Failed to convert {} to TOML", config_file));
+    toml_config.apply_env()?;
 
-    config
+    Config::from_toml(toml_config)
 }
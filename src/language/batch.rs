@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+
+use futures::future::{err, Future};
+use futures::stream::{self, Stream};
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiErrorResponse};
+use crate::language::grammar::{GramcheckRequest, GramcheckResponse};
+use crate::language::hyphenation::{HyphenationRequest, HyphenationResponse};
+use crate::language::speller::{SpellerOptions, SpellerRequest, SpellerResponse};
+use crate::metrics::track;
+use crate::server::state::State;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BatchRequest {
+    pub inputs: Vec<BatchInput>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchInput {
+    /// Identifies the input in the response, must be unique within the batch
+    pub id: String,
+    pub text: String,
+    pub language: String,
+    /// Checks to run on the text. Only the speller is run when left out.
+    #[serde(default)]
+    pub options: Option<BatchOptions>,
+}
+
+/// Each check that is given is run, with its own options
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchOptions {
+    #[serde(default)]
+    pub speller: Option<BatchSpellerOptions>,
+    #[serde(default)]
+    pub grammar: Option<BatchGrammarOptions>,
+    #[serde(default)]
+    pub hyphenation: bool,
+}
+
+/// The options of a speller request, see `SpellerRequest`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchSpellerOptions {
+    #[serde(default)]
+    pub suggest_correct: Option<bool>,
+    #[serde(default)]
+    pub include_correct: Option<bool>,
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(flatten)]
+    pub options: SpellerOptions,
+}
+
+/// The options of a grammar checker request, see `GramcheckRequest`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchGrammarOptions {
+    #[serde(default)]
+    pub ignore_tags: Vec<String>,
+    #[serde(default)]
+    pub enable_tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchResponse {
+    pub results: BTreeMap<String, BatchResult>,
+}
+
+/// The responses of the checks run on one input. A check that failed has its error in `errors`
+/// instead, keyed by the name of the check, and doesn't fail the rest of the batch.
+#[derive(Serialize, Clone, Default)]
+pub struct BatchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speller: Option<SpellerResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<GramcheckResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hyphenation: Option<HyphenationResponse>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<&'static str, ApiErrorResponse>,
+}
+
+/// A single check of a batch, along with the index of the input it is for
+enum Job {
+    Speller(usize, String, SpellerRequest),
+    Grammar(usize, String, GramcheckRequest),
+    Hyphenation(usize, String, HyphenationRequest),
+}
+
+enum Outcome {
+    Speller(Result<SpellerResponse, ApiError>),
+    Grammar(Result<GramcheckResponse, ApiError>),
+    Hyphenation(Result<HyphenationResponse, ApiError>),
+}
+
+impl Job {
+    fn run(self, state: &State) -> Box<dyn Future<Item = (usize, Outcome), Error = ApiError>> {
+        let functions = &state.language_functions;
+
+        match self {
+            Job::Speller(index, language, request) => Box::new(
+                track(
+                    "batch_speller",
+                    functions
+                        .spelling_suggestions
+                        .model_key(&request, &language),
+                    functions
+                        .spelling_suggestions
+                        .suggestions(request, &language),
+                )
+                .then(move |result| Ok::<_, ApiError>((index, Outcome::Speller(result)))),
+            ),
+            Job::Grammar(index, language, request) => Box::new(
+                track(
+                    "batch_grammar",
+                    functions.grammar_suggestions.model_key(&request, &language),
+                    functions
+                        .grammar_suggestions
+                        .suggestions(request, &language),
+                )
+                .then(move |result| Ok::<_, ApiError>((index, Outcome::Grammar(result)))),
+            ),
+            Job::Hyphenation(index, language, request) => Box::new(
+                track(
+                    "batch_hyphenation",
+                    functions
+                        .hyphenation_suggestions
+                        .model_key(&request, &language),
+                    functions
+                        .hyphenation_suggestions
+                        .suggestions(request, &language),
+                )
+                .then(move |result| Ok::<_, ApiError>((index, Outcome::Hyphenation(result)))),
+            ),
+        }
+    }
+}
+
+/// Runs the checks of every input, with at most `batch_concurrency` of them sent to the backends
+/// at a time. The results are in the order of the inputs.
+pub fn check_batch(
+    state: &State,
+    inputs: Vec<BatchInput>,
+) -> Box<dyn Future<Item = Vec<(String, BatchResult)>, Error = ApiError>> {
+    if let Err(e) = validate(state, &inputs) {
+        return Box::new(err(e));
+    }
+
+    let mut ids = Vec::with_capacity(inputs.len());
+    let mut jobs = vec![];
+
+    for (index, input) in inputs.into_iter().enumerate() {
+        let options = input.options.unwrap_or_else(|| BatchOptions {
+            speller: Some(BatchSpellerOptions::default()),
+            ..BatchOptions::default()
+        });
+
+        if let Some(speller) = options.speller {
+            jobs.push(Job::Speller(
+                index,
+                input.language.clone(),
+                SpellerRequest {
+                    suggest_correct: speller.suggest_correct.unwrap_or(true),
+                    include_correct: speller.include_correct.unwrap_or(true),
+                    variant: speller.variant,
+                    options: speller.options,
+                    ..SpellerRequest::new(input.text.clone())
+                },
+            ));
+        }
+
+        if let Some(grammar) = options.grammar {
+            jobs.push(Job::Grammar(
+                index,
+                input.language.clone(),
+                GramcheckRequest {
+                    ignore_tags: grammar.ignore_tags,
+                    enable_tags: grammar.enable_tags,
+                    ..GramcheckRequest::new(input.text.clone())
+                },
+            ));
+        }
+
+        if options.hyphenation {
+            jobs.push(Job::Hyphenation(
+                index,
+                input.language.clone(),
+                HyphenationRequest {
+                    text: input.text.clone(),
+                },
+            ));
+        }
+
+        ids.push(input.id);
+    }
+
+    let state = state.clone();
+    let concurrency = state.config.batch_concurrency;
+
+    // The checks are only sent once the stream gets to them, which keeps the mailboxes of the
+    // executors from being flooded by a single large batch
+    let outcomes = stream::iter_ok::<_, ApiError>(jobs)
+        .map(move |job| job.run(&state))
+        .buffer_unordered(concurrency)
+        .collect();
+
+    Box::new(outcomes.map(move |outcomes| {
+        let mut results = vec![BatchResult::default(); ids.len()];
+
+        for (index, outcome) in outcomes {
+            let result = &mut results[index];
+
+            match outcome {
+                Outcome::Speller(Ok(response)) => result.speller = Some(response),
+                Outcome::Grammar(Ok(response)) => result.grammar = Some(response),
+                Outcome::Hyphenation(Ok(response)) => result.hyphenation = Some(response),
+                Outcome::Speller(Err(e)) => {
+                    result.errors.insert("speller", e.to_response());
+                }
+                Outcome::Grammar(Err(e)) => {
+                    result.errors.insert("grammar", e.to_response());
+                }
+                Outcome::Hyphenation(Err(e)) => {
+                    result.errors.insert("hyphenation", e.to_response());
+                }
+            }
+        }
+
+        ids.into_iter().zip(results).collect()
+    }))
+}
+
+fn validate(state: &State, inputs: &[BatchInput]) -> Result<(), ApiError> {
+    let max_inputs = state.config.batch_max_inputs;
+
+    if inputs.len() > max_inputs {
+        return Err(ApiError::UnprocessableInput(format!(
+            "A batch may have at most {} inputs, got {}",
+            max_inputs,
+            inputs.len()
+        )));
+    }
+
+    let mut ids = HashSet::with_capacity(inputs.len());
+
+    for input in inputs {
+        if !ids.insert(input.id.as_str()) {
+            return Err(ApiError::UnprocessableInput(format!(
+                "Duplicate input id {}",
+                input.id
+            )));
+        }
+    }
+
+    Ok(())
+}
@@ -1,14 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
 
+use lazy_static::lazy_static;
 use log::warn;
 use serde::Serialize;
 
+use crate::language::models::{ArchiveInfo, ModelStatus};
+use crate::language::tags::fallback_chain;
+
 include!(concat!(env!("OUT_DIR"), "/autonyms.rs"));
 
-#[derive(Clone, Copy)]
+lazy_static! {
+    /// `LANGUAGE_AUTONYMS` is keyed by the ISO 639-1 tag of languages that have one, so their
+    /// ISO 639-3 tags are looked up here
+    static ref TAG1_BY_TAG3: HashMap<&'static str, &'static str> = LANGUAGE_AUTONYMS
+        .values()
+        .filter_map(|record| record.tag1.map(|tag1| (record.tag3, tag1)))
+        .collect();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DataFileType {
     Grammar,
     Spelling,
@@ -25,24 +38,92 @@ pub struct AvailableLanguagesByType {
 #[derive(Serialize)]
 pub struct AvailableLanguagesResponse {
     pub available: AvailableLanguagesByType,
+    /// Loaded speller variants by language
+    pub variants: BTreeMap<String, Vec<SpellerVariant>>,
+    pub languages: Vec<LanguageInfo>,
+}
+
+/// A language with models loaded, and what is known about it
+#[derive(Serialize, Debug)]
+pub struct LanguageInfo {
+    /// The tag the language's models are loaded under, e.g. `se`
+    pub tag: String,
+    pub tag1: Option<&'static str>,
+    pub tag3: Option<&'static str>,
+    /// English name
+    pub name: Option<&'static str>,
+    pub autonym: Option<&'static str>,
+    /// Name in the requested UI language, when it is known in it
+    pub localized_name: Option<&'static str>,
+    /// Where the names come from
+    pub source: Option<&'static str>,
+    /// Types of models loaded for the language
+    pub capabilities: Vec<&'static str>,
+    pub models: Vec<LanguageModel>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LanguageModel {
+    #[serde(rename = "type")]
+    pub data_type: &'static str,
+    /// The file stem the model was loaded from
+    pub tag: String,
+    /// Variant or version of the model, e.g. `2.1` for `smj@2.1`
+    pub variant: Option<String>,
+    pub size: u64,
+    /// Modification time of the file in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Title of the speller archive in the requested UI language
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveInfo>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SpellerVariant {
+    /// The file stem the variant was loaded from, e.g. `se-x-school`
+    pub tag: String,
+    /// The variant name clients ask for, absent for a plain `se.zhfst`
+    pub variant: Option<String>,
+    /// Whether this variant is used when no variant is asked for
+    pub default: bool,
+    pub size: u64,
+    pub modified: Option<u64>,
 }
 
 impl DataFileType {
-    pub fn as_ext(&self) -> &str {
+    /// Extensions of the data files of this type, the preferred format first
+    pub fn as_exts(&self) -> &'static [&'static str] {
         match self {
-            DataFileType::Grammar => "zcheck",
-            DataFileType::Spelling => "zhfst",
-            DataFileType::Hyphenation => "hfstol",
+            DataFileType::Grammar => &["zcheck"],
+            DataFileType::Spelling => &["bhfst", "zhfst"],
+            DataFileType::Hyphenation => &["hfstol"],
         }
     }
 
-    pub fn as_dir(&self) -> &str {
+    /// Whether `path` has the extension of a data file of this type
+    pub fn matches(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map_or(false, |ext| self.as_exts().contains(&ext))
+    }
+
+    pub fn as_dir(&self) -> &'static str {
         match self {
             DataFileType::Grammar => "grammar",
             DataFileType::Spelling => "spelling",
             DataFileType::Hyphenation => "hyphenation",
         }
     }
+
+    pub fn from_dir(dir: &str) -> Option<Self> {
+        match dir {
+            "grammar" => Some(DataFileType::Grammar),
+            "spelling" => Some(DataFileType::Spelling),
+            "hyphenation" => Some(DataFileType::Hyphenation),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -54,42 +135,29 @@ struct Record {
     source: Option<&'static str>,
 }
 
-pub fn available_languages(
-    data_file_dir: &Path,
-    data_type: DataFileType,
-) -> HashMap<String, String> {
-    let lang_data_files = match get_data_files(data_file_dir, data_type) {
-        Ok(v) => v,
-        Err(_) => Vec::new(),
-    };
-
-    let lang_keys: Vec<String> = lang_data_files
+/// Names the languages of loaded models by their autonym, or their English name if there is none
+pub fn available_languages(models: &[ModelStatus]) -> HashMap<String, String> {
+    // Variants are listed under the language they belong to
+    let lang_keys: Vec<&str> = models
         .iter()
-        .map(|p| {
-            p.file_stem()
-                .expect("Somehow this doesn't have a filestem")
-                .to_str()
-                .expect("Somehow this OsStr cannot be converted to str")
-                .to_owned()
-        })
+        .map(|model| split_variant(&model.language).0)
         .collect();
 
     let result: HashMap<String, String> = lang_keys
         .iter()
-        .map(|k| {
-            if !LANGUAGE_AUTONYMS.contains_key::<str>(k) {
+        .map(|&k| match record(k) {
+            None => {
                 warn!("Key {} not found in autonyms file", k);
-                (k.clone(), k.clone())
-            } else {
-                let record = LANGUAGE_AUTONYMS.get::<str>(k).unwrap();
-
+                (k.to_owned(), k.to_owned())
+            }
+            Some(record) => {
                 let value = record
                     .autonym
                     .or(record.name)
                     .or(Some(record.tag3))
                     .unwrap();
 
-                (k.clone(), value.to_owned())
+                (k.to_owned(), value.to_owned())
             }
         })
         .collect();
@@ -97,25 +165,321 @@ pub fn available_languages(
     result
 }
 
+/// Describes every language that has models loaded, with names localized to the first of
+/// `ui_languages` they are known in
+pub fn languages(
+    models: Vec<(DataFileType, Vec<ModelStatus>)>,
+    ui_languages: &[String],
+) -> Vec<LanguageInfo> {
+    let mut languages: BTreeMap<String, LanguageInfo> = BTreeMap::new();
+
+    for (data_type, type_models) in models {
+        for model in type_models {
+            let (language, variant) = split_variant(&model.language);
+
+            let info = languages
+                .entry(language.to_owned())
+                .or_insert_with(|| language_info(language, ui_languages));
+
+            if !info.capabilities.contains(&data_type.as_dir()) {
+                info.capabilities.push(data_type.as_dir());
+            }
+
+            info.models.push(LanguageModel {
+                data_type: data_type.as_dir(),
+                tag: model.language.clone(),
+                variant: variant.map(str::to_owned),
+                size: model.size,
+                modified: model.modified,
+                title: model
+                    .archive
+                    .as_ref()
+                    .and_then(|archive| archive.title(ui_languages))
+                    .map(str::to_owned),
+                archive: model.archive.clone(),
+            });
+        }
+    }
+
+    languages.into_iter().map(|(_, info)| info).collect()
+}
+
+fn language_info(tag: &str, ui_languages: &[String]) -> LanguageInfo {
+    let record = record(tag);
+
+    LanguageInfo {
+        tag: tag.to_owned(),
+        tag1: record.and_then(|record| record.tag1),
+        tag3: record.map(|record| record.tag3),
+        name: record.and_then(|record| record.name),
+        autonym: record.and_then(|record| record.autonym),
+        localized_name: record.and_then(|record| localized_name(record, ui_languages)),
+        source: record.and_then(|record| record.source),
+        capabilities: vec![],
+        models: vec![],
+    }
+}
+
+/// Only the autonym and the English name of languages are known, so names can only be
+/// localized to the language itself and to English
+fn localized_name(record: &Record, ui_languages: &[String]) -> Option<&'static str> {
+    ui_languages
+        .iter()
+        .flat_map(|tag| fallback_chain(tag))
+        .find_map(|candidate| {
+            if Some(candidate.as_str()) == record.tag1 || candidate == record.tag3 {
+                record.autonym
+            } else if candidate == "en" || candidate == "eng" {
+                record.name
+            } else {
+                None
+            }
+        })
+}
+
+/// Looks up a language by either of its ISO 639 tags
+fn record(tag: &str) -> Option<&'static Record> {
+    LANGUAGE_AUTONYMS.get::<str>(tag).or_else(|| {
+        TAG1_BY_TAG3
+            .get(tag)
+            .and_then(|tag1| LANGUAGE_AUTONYMS.get::<str>(*tag1))
+    })
+}
+
+/// The other ISO 639 tag of a language, e.g. `sme` for `se` and `se` for `sme`
+pub fn iso639_alias(language: &str) -> Option<&'static str> {
+    match LANGUAGE_AUTONYMS.get::<str>(language) {
+        Some(record) if record.tag1.is_some() => Some(record.tag3),
+        Some(_) => None,
+        None => TAG1_BY_TAG3.get(language).cloned(),
+    }
+}
+
+/// Groups loaded speller models by language, marking the variant used by default
+pub fn speller_variants(models: Vec<ModelStatus>) -> BTreeMap<String, Vec<SpellerVariant>> {
+    let mut variants: BTreeMap<String, Vec<SpellerVariant>> = BTreeMap::new();
+
+    for model in &models {
+        let (language, variant) = split_variant(&model.language);
+        let default = default_variant(models.iter().map(|m| m.language.as_str()), language)
+            == Some(model.language.as_str());
+
+        variants
+            .entry(language.to_owned())
+            .or_default()
+            .push(SpellerVariant {
+                tag: model.language.clone(),
+                variant: variant.map(str::to_owned),
+                default,
+                size: model.size,
+                modified: model.modified,
+            });
+    }
+
+    for language_variants in variants.values_mut() {
+        language_variants.sort_by(|a, b| a.tag.cmp(&b.tag));
+    }
+
+    variants
+}
+
+/// Splits a data file stem into its language and variant, e.g. `se-x-school` into `se` and
+/// `school`, or `smj@2.1` into `smj` and `2.1`
+pub fn split_variant(stem: &str) -> (&str, Option<&str>) {
+    if let Some(index) = stem.find('@') {
+        return (&stem[..index], Some(&stem[index + 1..]));
+    }
+
+    if let Some(index) = stem.find("-x-") {
+        return (&stem[..index], Some(&stem[index + 3..]));
+    }
+
+    (stem, None)
+}
+
+/// Picks the model used for `language` when no variant is asked for: the one without a
+/// variant, or else the first variant in sorted order
+pub fn default_variant<'a, I>(keys: I, language: &str) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut candidates: Vec<&str> = keys
+        .into_iter()
+        .filter(|key| split_variant(key).0 == language)
+        .collect();
+    candidates.sort();
+
+    candidates
+        .iter()
+        .find(|key| split_variant(key).1.is_none())
+        .or_else(|| candidates.first())
+        .copied()
+}
+
+/// Finds the model of a variant of `language`, which may be given with or without the `x-`
+/// private use prefix
+pub fn find_variant<'a, I>(keys: I, language: &str, variant: &str) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let variant = if variant.starts_with("x-") {
+        &variant[2..]
+    } else {
+        variant
+    };
+
+    keys.into_iter()
+        .find(|key| split_variant(key) == (language, Some(variant)))
+}
+
+/// The file a model should be loaded from when the data file at `path` exists in several
+/// formats, e.g. `se.bhfst` rather than `se.zhfst`
+pub fn preferred_data_file(path: &Path, data_type: DataFileType) -> Option<PathBuf> {
+    let stem = path.file_stem()?;
+
+    data_type
+        .as_exts()
+        .iter()
+        .map(|ext| {
+            // Not `with_extension`, as stems such as `smj@2.1` contain dots
+            let mut file_name = stem.to_os_string();
+            file_name.push(".");
+            file_name.push(ext);
+            path.with_file_name(file_name)
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+/// Lists the data files of `data_type` in `dir`, the directory configured for that type, and
+/// in its subdirectories when `recursive` is set. Of a model that exists in several formats,
+/// only the file in the preferred one is listed. Models are keyed by the file stem alone, so of
+/// files with the same stem in different subdirectories only the first one in path order is
+/// listed, and the others are logged.
 pub fn get_data_files(
-    data_file_dir: &Path,
+    dir: &Path,
     data_type: DataFileType,
+    recursive: bool,
 ) -> std::io::Result<Vec<PathBuf>> {
-    let dir = get_typed_data_dir(data_file_dir, data_type);
+    let mut paths = find_data_files(dir, data_type, recursive)?;
+    paths.sort();
+
+    let mut files: Vec<PathBuf> = vec![];
+
+    for path in paths {
+        let listed = files
+            .iter()
+            .find(|file| file.file_stem() == path.file_stem());
+
+        match listed {
+            Some(file) => warn!(
+                "Not loading `{}`, `{}` is loaded for the same language",
+                path.display(),
+                file.display()
+            ),
+            None => files.push(path),
+        }
+    }
 
-    let extension = data_type.as_ext();
+    Ok(files)
+}
 
-    let paths = fs::read_dir(dir)
+fn find_data_files(
+    dir: &Path,
+    data_type: DataFileType,
+    recursive: bool,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+
+    for path in fs::read_dir(dir)
         .map_err(|_| Error::new(ErrorKind::InvalidInput, "Not a directory"))?
         .filter_map(|x| x.ok())
         .map(|x| x.path())
-        .filter(|path| !path.is_dir())
-        .filter(|path| path.extension().unwrap_or_default() == extension)
-        .collect();
+    {
+        if path.is_dir() {
+            if recursive {
+                paths.extend(find_data_files(&path, data_type, recursive).unwrap_or_default());
+            }
+        } else if data_type.matches(&path)
+            && preferred_data_file(&path, data_type).as_ref() == Some(&path)
+        {
+            paths.push(path);
+        }
+    }
 
     Ok(paths)
 }
 
-pub fn get_typed_data_dir(data_file_dir: &Path, data_type: DataFileType) -> PathBuf {
-    data_file_dir.join(data_type.as_dir())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variants() {
+        assert_eq!(split_variant("se"), ("se", None));
+        assert_eq!(split_variant("se-x-school"), ("se", Some("school")));
+        assert_eq!(split_variant("smj@2.1"), ("smj", Some("2.1")));
+
+        let keys = vec!["se-x-standard", "se", "se-x-school", "smj@2.1", "smj@1.0"];
+        assert_eq!(default_variant(keys.clone(), "se"), Some("se"));
+        assert_eq!(default_variant(keys.clone(), "smj"), Some("smj@1.0"));
+        assert_eq!(default_variant(keys.clone(), "sma"), None);
+        assert_eq!(
+            find_variant(keys.clone(), "se", "x-school"),
+            Some("se-x-school")
+        );
+        assert_eq!(find_variant(keys.clone(), "smj", "2.1"), Some("smj@2.1"));
+        assert_eq!(find_variant(keys, "se", "2.1"), None);
+    }
+
+    #[test]
+    fn test_preferred_data_file() {
+        let dir = std::env::temp_dir().join("divvun-api-test-preferred-data-file");
+        fs::create_dir_all(&dir).unwrap();
+
+        let zhfst = dir.join("smj@2.1.zhfst");
+        let bhfst = dir.join("smj@2.1.bhfst");
+        fs::write(&zhfst, b"").unwrap();
+        let _ = fs::remove_file(&bhfst);
+
+        let spelling = DataFileType::Spelling;
+        assert_eq!(preferred_data_file(&zhfst, spelling), Some(zhfst.clone()));
+        assert_eq!(
+            get_data_files(&dir, spelling, false).unwrap(),
+            vec![zhfst.clone()]
+        );
+
+        fs::write(&bhfst, b"").unwrap();
+        assert_eq!(preferred_data_file(&zhfst, spelling), Some(bhfst.clone()));
+        assert_eq!(
+            get_data_files(&dir, spelling, false).unwrap(),
+            vec![bhfst.clone()]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_nested_data_files() {
+        let dir = std::env::temp_dir().join("divvun-api-test-nested-data-files");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+
+        let top = dir.join("sma.zhfst");
+        let first = dir.join("a").join("se.zhfst");
+        let duplicate = dir.join("b").join("se.zhfst");
+        let nested = dir.join("b").join("smj.bhfst");
+
+        for path in &[&top, &first, &duplicate, &nested] {
+            fs::write(path, b"").unwrap();
+        }
+
+        let spelling = DataFileType::Spelling;
+        assert_eq!(
+            get_data_files(&dir, spelling, true).unwrap(),
+            vec![first.clone(), nested.clone(), top.clone()]
+        );
+        assert_eq!(get_data_files(&dir, spelling, false).unwrap(), vec![top]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,43 +1,126 @@
 use std::collections::BTreeMap;
 use std::io::{BufRead, BufReader, Error, Write};
 use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
-use futures::future::{err, ok, Future};
+use actix_web::web;
+use futures::future::{err, join_all, ok, Either, Future};
 use hashbrown::HashMap;
 use log::{debug, error, info, warn};
 use parking_lot::RwLock;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::config::GramcheckPoolConfig;
 use crate::error::ApiError;
+use crate::language::data_files::DataFileType;
+use crate::language::models::{LoadedModel, ModelInfo, ModelStatus};
+use crate::language::tags::resolve;
+use crate::language::text::utf16_len;
+use crate::metrics::{count_restart, Queued};
 use crate::server::state::{LanguageSuggestions, UnhoistFutureExt};
 
-pub struct GramcheckExecutor {
+/// Requests in a row that may fail before an executor stops respawning its child
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Text checked by a newly started `divvun-checker` before it is put in service
+const PROBE_TEXT: &str = "test";
+
+/// A running `divvun-checker` child. Its stdout is read on a separate thread so that replies
+/// can be waited for with a deadline.
+pub struct CheckerProcess {
     pub child: Child,
+    pub lines: Receiver<Result<String, Error>>,
+}
+
+impl CheckerProcess {
+    fn start(data_file_path: &str) -> Result<Self, Error> {
+        let mut child = start_divvun_checker(data_file_path)?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| to_io_err("Failed to open stdout"))?;
+
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+
+            loop {
+                let mut line = String::new();
+
+                match stdout.read_line(&mut line) {
+                    // EOF, the child has exited
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if tx.send(Ok(line)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(CheckerProcess { child, lines: rx })
+    }
+}
+
+pub struct GramcheckExecutor {
+    pub process: Option<CheckerProcess>,
     pub path: String,
     pub language: String,
     pub terminated: bool,
+    pub timeout: Duration,
+    pub restarts: Arc<AtomicUsize>,
+    pub consecutive_failures: u32,
+    pub quarantined_until: Option<Instant>,
+    /// Starts a new child after the previous one failed
+    pub spawn: fn(&str) -> Result<CheckerProcess, Error>,
 }
 
 impl GramcheckExecutor {
-    pub fn new(data_file_path: &str, language: &str) -> Result<Self, Error> {
-        let child = start_divvun_checker(data_file_path)?;
+    pub fn new(
+        data_file_path: &str,
+        language: &str,
+        timeout: Duration,
+        restarts: Arc<AtomicUsize>,
+    ) -> Result<Self, Error> {
+        let process = CheckerProcess::start(data_file_path)?;
 
         Ok(Self {
-            child,
+            process: Some(process),
             path: data_file_path.to_owned(),
             language: language.to_owned(),
             terminated: false,
+            timeout,
+            restarts,
+            consecutive_failures: 0,
+            quarantined_until: None,
+            spawn: CheckerProcess::start,
         })
     }
 
     fn kill_child(&mut self) {
-        match self.child.kill() {
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => return,
+        };
+
+        match process.child.kill() {
             Ok(_) => {
-                // This blocks and may cause issues if the child doesn't properly die
-                match self.child.wait() {
+                // The child has been sent SIGKILL, so this only waits for it to be reaped
+                match process.child.wait() {
                     Ok(_) => debug!("Child killed"),
                     Err(e) => error!("Failed to kill child: {} while waiting", e),
                 }
@@ -45,15 +128,219 @@ impl GramcheckExecutor {
             Err(e) => error!("Failed to kill child: {}", e),
         };
     }
+
+    /// Kills the current child after it crashed, hung or misbehaved and starts a new one,
+    /// unless it keeps failing, in which case the language is quarantined with an
+    /// exponentially growing backoff
+    fn fail(&mut self) {
+        self.kill_child();
+        self.consecutive_failures += 1;
+
+        if self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            let backoff = backoff(self.consecutive_failures);
+
+            error!(
+                "Grammar checker for language `{}` failed {} times in a row, quarantining for {:?}",
+                &self.language, self.consecutive_failures, backoff
+            );
+
+            self.quarantined_until = Some(Instant::now() + backoff);
+            return;
+        }
+
+        warn!(
+            "Restarting grammar checker for language `{}`",
+            &self.language
+        );
+
+        if let Err(e) = self.respawn() {
+            error!(
+                "Failed to spawn child for language `{}`: {}",
+                &self.language, e
+            );
+        }
+    }
+
+    /// Starts a child in place of one that failed, counting it as a restart
+    fn respawn(&mut self) -> Result<(), Error> {
+        self.restarts.fetch_add(1, Ordering::SeqCst);
+        count_restart(DataFileType::Grammar, &self.language);
+        self.process = Some((self.spawn)(&self.path)?);
+        Ok(())
+    }
+
+    /// Fails while the language is quarantined, and lifts the quarantine once it has ended
+    fn check_quarantine(&mut self) -> Result<(), ApiError> {
+        if let Some(until) = self.quarantined_until {
+            if Instant::now() < until {
+                return Err(ApiError::Unavailable(format!(
+                    "Grammar checker for language {} is temporarily unavailable",
+                    &self.language
+                )));
+            }
+
+            self.quarantined_until = None;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the running child, starting a new one if the previous one could not be
+    /// respawned or the quarantine has ended. Failing to start one is left to the caller to
+    /// count as a failure.
+    fn process(&mut self) -> Result<&mut CheckerProcess, ApiError> {
+        if self.process.is_none() {
+            self.respawn()?;
+        }
+
+        let language = &self.language;
+
+        self.process.as_mut().ok_or_else(|| {
+            ApiError::Unavailable(format!(
+                "Grammar checker for language {} is temporarily unavailable",
+                language
+            ))
+        })
+    }
+
+    /// Checks a short text to make sure the child has loaded its data file and answers with
+    /// something that can be parsed
+    fn probe(&mut self) -> Result<(), ApiError> {
+        let line = self.check_line(PROBE_TEXT)?;
+
+        serde_json::from_str::<GramcheckResponse>(&line)
+            .map(|_| ())
+            .map_err(|e| {
+                ApiError::Internal(format!(
+                    "Grammar checker {} answered a test check with '{}': {}",
+                    &self.path,
+                    line.trim(),
+                    e
+                ))
+            })
+    }
+
+    /// Sends a single line to the child and waits for its reply for at most `timeout`
+    fn check_line(&mut self, line: &str) -> Result<String, ApiError> {
+        let timeout = self.timeout;
+        let language = self.language.clone();
+        let process = self.process()?;
+
+        let stdin = match process.child.stdin.as_mut() {
+            Some(r) => r,
+            _ => {
+                return Err(ApiError::BackendFailure("Failed to open stdin".into()));
+            }
+        };
+
+        stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| stdin.write_all(b"\n"))
+            .and_then(|_| stdin.flush())?;
+
+        match process.lines.recv_timeout(timeout) {
+            Ok(Ok(line)) => Ok(line),
+            Ok(Err(err)) => Err(err.into()),
+            Err(RecvTimeoutError::Timeout) => Err(ApiError::Timeout(format!(
+                "Grammar checker for language {} timed out after {:?}",
+                &language, timeout
+            ))),
+            Err(RecvTimeoutError::Disconnected) => Err(ApiError::BackendFailure(format!(
+                "Grammar checker for language {} exited unexpectedly",
+                &language
+            ))),
+        }
+    }
+
+    /// Checks every paragraph of a request. A request that fails counts as a single failure
+    /// towards quarantining the language, however it failed.
+    fn check(&mut self, msg: GramcheckRequest) -> Result<GramcheckResponse, ApiError> {
+        self.check_quarantine()?;
+
+        match self.check_paragraphs(&msg) {
+            Ok(errs) => {
+                self.consecutive_failures = 0;
+
+                Ok(GramcheckResponse {
+                    text: msg.text,
+                    language: self.language.clone(),
+                    errs,
+                })
+            }
+            Err(e) => {
+                // Whether the child could not be started, did not answer or answered with
+                // something that could not be parsed, it is restarted, as its next answer
+                // can't be trusted to be for the next request
+                self.fail();
+                Err(e)
+            }
+        }
+    }
+
+    fn check_paragraphs(
+        &mut self,
+        msg: &GramcheckRequest,
+    ) -> Result<Vec<GramcheckErrResponse>, ApiError> {
+        let mut errs = Vec::new();
+        let mut offset = 0;
+
+        // divvun-checker answers one line of JSON per line of input, so each paragraph
+        // is checked separately and its error indices shifted back into the full text
+        for paragraph in msg.text.split('\n') {
+            // divvun-checker indexes errors in UTF-16 code units
+            let paragraph_offset = offset;
+            offset += utf16_len(paragraph) as u32 + 1;
+
+            let cleaned_paragraph = paragraph.trim_end_matches('\r');
+            if cleaned_paragraph.trim().is_empty() {
+                continue;
+            }
+
+            let line = self.check_line(cleaned_paragraph)?;
+
+            let response: GramcheckResponse = serde_json::from_str(&line).map_err(|err| {
+                ApiError::BackendFailure(format!("error: {:?}, line: '{}'", &err, &line))
+            })?;
+
+            // divvun-checker has no way of toggling rules per line, so disabled rules are
+            // filtered out of its output instead
+            errs.extend(
+                response
+                    .errs
+                    .into_iter()
+                    .filter(|err| msg.is_enabled(&err.error_code))
+                    .map(|mut err| {
+                        err.start_index += paragraph_offset;
+                        err.end_index += paragraph_offset;
+                        err
+                    }),
+            );
+        }
+
+        Ok(errs)
+    }
+}
+
+impl Drop for GramcheckExecutor {
+    fn drop(&mut self) {
+        self.kill_child();
+    }
+}
+
+fn backoff(consecutive_failures: u32) -> Duration {
+    let exponent = (consecutive_failures - MAX_CONSECUTIVE_FAILURES).min(16);
+    std::cmp::min(BASE_BACKOFF * 2u32.pow(exponent), MAX_BACKOFF)
 }
 
 fn start_divvun_checker(data_file_path: &str) -> Result<Child, Error> {
+    // stderr is discarded rather than piped, as nothing reads it and a full pipe
+    // would block the child
     let process = Command::new("divvun-checker")
         .arg("-a")
         .arg(data_file_path)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
+        .stderr(Stdio::null())
         .spawn()?;
 
     Ok(process)
@@ -70,21 +357,38 @@ impl Supervised for GramcheckExecutor {
 
             // Killing previous child. Reusing the child would be more eco-friendly,
             // but there seems no reliable way to check the status of the child
-            self.kill_child();
-            self.child = match start_divvun_checker(&self.path) {
-                Ok(child) => child,
-                Err(e) => {
-                    error!("Failed to spawn child for language `{}`!", &self.language);
-                    panic!(e)
-                }
-            }
+            self.fail();
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct GramcheckRequest {
     pub text: String,
+    /// Error tags, as listed by the grammar preferences, to leave out of the response
+    #[serde(default)]
+    pub ignore_tags: Vec<String>,
+    /// When not empty, only errors with these tags are returned
+    #[serde(default)]
+    pub enable_tags: Vec<String>,
+}
+
+impl GramcheckRequest {
+    pub fn new(text: String) -> Self {
+        GramcheckRequest {
+            text,
+            ignore_tags: vec![],
+            enable_tags: vec![],
+        }
+    }
+
+    fn is_enabled(&self, error_code: &str) -> bool {
+        if self.ignore_tags.iter().any(|tag| tag == error_code) {
+            return false;
+        }
+
+        self.enable_tags.is_empty() || self.enable_tags.iter().any(|tag| tag == error_code)
+    }
 }
 
 impl Message for GramcheckRequest {
@@ -94,48 +398,8 @@ impl Message for GramcheckRequest {
 impl Handler<GramcheckRequest> for GramcheckExecutor {
     type Result = Result<GramcheckResponse, ApiError>;
 
-    fn handle(&mut self, msg: GramcheckRequest, ctx: &mut Self::Context) -> Self::Result {
-        let stdin = match self.child.stdin.as_mut() {
-            Some(r) => r,
-            _ => {
-                ctx.stop();
-                return Err(ApiError {
-                    message: "Failed to open stdin".into(),
-                });
-            }
-        };
-
-        let stdout = match self.child.stdout.as_mut() {
-            Some(r) => r,
-            _ => {
-                ctx.stop();
-                return Err(ApiError {
-                    message: "Failed to open stdout".into(),
-                });
-            }
-        };
-
-        let mut stdout = BufReader::new(stdout);
-
-        let cleaned_msg = msg.text.split('\n').next().ok_or_else(|| ApiError {
-            message: "Invalid input".into(),
-        })?;
-
-        let mut line = String::new();
-
-        if let Err(err) = stdin
-            .write_all(cleaned_msg.as_bytes())
-            .and_then(|_| stdin.write_all(b"\n"))
-            .and_then(|_| stdout.read_line(&mut line))
-        {
-            // If anything here fails, restart the runner
-            ctx.stop();
-            return Err(err.into());
-        }
-
-        serde_json::from_str(&line).map_err(|err| ApiError {
-            message: format!("error: {:?}, line: '{}'", &err, &line),
-        })
+    fn handle(&mut self, msg: GramcheckRequest, _: &mut Self::Context) -> Self::Result {
+        self.check(msg)
     }
 }
 
@@ -166,6 +430,7 @@ impl Handler<Die> for GramcheckExecutor {
 
 #[derive(Deserialize, Serialize)]
 pub struct GramcheckPreferencesResponse {
+    pub language: String,
     pub error_tags: BTreeMap<String, String>,
 }
 
@@ -180,14 +445,81 @@ pub struct GramcheckErrResponse {
     pub title: String,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 pub struct GramcheckResponse {
     pub text: String,
+    /// Tag of the model that handled the request, which may differ from the requested one.
+    /// divvun-checker leaves it out of its own output.
+    #[serde(default)]
+    pub language: String,
     pub errs: Vec<GramcheckErrResponse>,
 }
 
+/// A single `divvun-checker` backed executor in a language's pool, along with the number of
+/// requests that were sent to it and have not completed yet
+#[derive(Clone)]
+pub struct PoolMember {
+    pub addr: Addr<GramcheckExecutor>,
+    pub in_flight: Arc<AtomicUsize>,
+}
+
+#[derive(Clone)]
+pub struct GramcheckPool {
+    pub members: Vec<PoolMember>,
+    pub queue_size: usize,
+}
+
+/// Counts a request as in flight on a pool member for as long as it is alive. It is moved into
+/// the future of the request, so that requests whose future is dropped before completing, such
+/// as those of clients that disconnected, are taken off the count as well.
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        InFlight(Arc::clone(count))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl GramcheckPool {
+    fn in_flight(&self) -> usize {
+        self.members
+            .iter()
+            .map(|member| member.in_flight.load(Ordering::SeqCst))
+            .sum()
+    }
+
+    fn least_busy(&self) -> Option<&PoolMember> {
+        self.members
+            .iter()
+            .min_by_key(|member| member.in_flight.load(Ordering::SeqCst))
+    }
+
+    /// Picks the member with the fewest requests in flight and counts a request on it, or
+    /// returns `None` when `queue_size` requests are in flight on the whole pool already
+    fn reserve(&self) -> Option<(&PoolMember, InFlight)> {
+        if self.in_flight() >= self.queue_size {
+            return None;
+        }
+
+        self.least_busy()
+            .map(|member| (member, InFlight::new(&member.in_flight)))
+    }
+
+    fn is_alive(&self) -> bool {
+        self.members.iter().all(|member| member.addr.connected())
+    }
+}
+
 pub struct AsyncGramchecker {
-    pub gramcheckers: Arc<RwLock<HashMap<String, Addr<GramcheckExecutor>>>>,
+    pub gramcheckers: Arc<RwLock<HashMap<String, LoadedModel<GramcheckPool>>>>,
+    pub pool_config: GramcheckPoolConfig,
 }
 
 impl LanguageSuggestions for AsyncGramchecker {
@@ -201,45 +533,142 @@ impl LanguageSuggestions for AsyncGramchecker {
     ) -> Box<dyn Future<Item = Self::Response, Error = ApiError>> {
         let gramcheckers = self.gramcheckers.read();
 
-        let gramchecker = match gramcheckers.get(language) {
-            Some(s) => s,
+        let (language, pool) = match resolve(gramcheckers.keys().map(String::as_str), language)
+            .and_then(|key| gramcheckers.get_key_value(key))
+        {
+            Some((key, s)) => (key.as_str(), &s.executor),
             None => {
-                return Box::new(err(ApiError {
-                    message: format!("No grammar checker available for language {}", &language),
-                }));
+                return Box::new(err(ApiError::LanguageNotFound(format!(
+                    "No grammar checker available for language {}",
+                    &language
+                ))));
             }
         };
 
+        // Pools are never empty, so this only fails when the pool is busy
+        let (member, in_flight) = match pool.reserve() {
+            Some(reserved) => reserved,
+            None => {
+                return Box::new(err(ApiError::Unavailable(format!(
+                    "Grammar checker for language {} is busy",
+                    &language
+                ))));
+            }
+        };
+
+        let queued = Queued::new(DataFileType::Grammar, language);
+
         let language = language.to_owned();
 
         Box::new(
-            gramchecker
+            member
+                .addr
                 .send(message)
-                .map_err(move |err| ApiError {
-                    message: format!(
+                .then(move |res| {
+                    drop(in_flight);
+                    drop(queued);
+                    res
+                })
+                .map_err(move |err| {
+                    ApiError::Unavailable(format!(
                         "Something failed in the message delivery process for language {}: {}",
                         &language, err
-                    ),
+                    ))
                 })
                 .unhoist(),
         )
     }
 
-    fn add(&self, language: &str, path: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
-        info!("Adding Grammar Checker for {}", language);
+    fn model_key(&self, _message: &Self::Request, language: &str) -> Option<String> {
+        let gramcheckers = self.gramcheckers.read();
+        resolve(gramcheckers.keys().map(String::as_str), language).map(str::to_owned)
+    }
 
-        let mut gramcheckers = self.gramcheckers.write();
+    fn add(&self, language: &str, path: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
+        let size = self.pool_config.size_for(language);
 
-        let gramchecker_path = path.to_owned();
-        let owned_language = language.to_owned();
-        let gramchecker = actix::Supervisor::start_in_arbiter(&actix::Arbiter::new(), move |_| {
-            GramcheckExecutor::new(&gramchecker_path, &owned_language)
-                .expect(&format!("not found: {}", &gramchecker_path))
-        });
+        info!(
+            "Adding Grammar Checker for {} with {} process(es)",
+            language, size
+        );
 
-        gramcheckers.insert(language.to_owned(), gramchecker);
+        let timeout = Duration::from_millis(self.pool_config.timeout_ms);
+        let queue_size = self.pool_config.queue_size;
+        let gramcheckers = Arc::clone(&self.gramcheckers);
+        let language = language.to_owned();
+        let owned_language = language.clone();
+        let path = path.to_owned();
+
+        // Every process is started and checked on the blocking thread pool before touching the
+        // running pool, so that a bad file leaves the current one serving
+        let started = web::block(move || {
+            let info = ModelInfo::new(&path);
+
+            let executors = (0..size)
+                .map(|_| {
+                    let mut executor = GramcheckExecutor::new(
+                        &path,
+                        &owned_language,
+                        timeout,
+                        Arc::clone(&info.restarts),
+                    )
+                    .map_err(|e| {
+                        ApiError::Internal(format!(
+                            "Failed to start divvun-checker for {}: {}",
+                            path, e
+                        ))
+                    })?;
+                    executor.probe()?;
+                    Ok(executor)
+                })
+                .collect::<Result<Vec<_>, ApiError>>()
+                .map_err(|e| {
+                    error!("Failed to load grammar checker {}: {}", path, e);
+                    e
+                })?;
 
-        Box::new(ok(()))
+            Ok::<_, ApiError>((info, executors))
+        })
+        .map_err(ApiError::from);
+
+        Box::new(started.and_then(move |(info, executors)| {
+            let members = executors
+                .into_iter()
+                .map(|executor| PoolMember {
+                    addr: actix::Supervisor::start_in_arbiter(&actix::Arbiter::new(), move |_| {
+                        executor
+                    }),
+                    in_flight: Arc::new(AtomicUsize::new(0)),
+                })
+                .collect();
+
+            let previous = gramcheckers.write().insert(
+                language,
+                LoadedModel {
+                    executor: GramcheckPool {
+                        members,
+                        queue_size,
+                    },
+                    info,
+                },
+            );
+
+            match previous {
+                // Requests already queued on the replaced pool are answered before it stops
+                Some(previous) => Either::A(
+                    join_all(
+                        previous
+                            .executor
+                            .members
+                            .iter()
+                            .map(|member| member.addr.send(Die))
+                            .collect::<Vec<_>>(),
+                    )
+                    .then(|_| ok::<(), ApiError>(())),
+                ),
+                None => Either::B(ok(())),
+            }
+        }))
     }
 
     fn remove(&self, language: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
@@ -247,36 +676,69 @@ impl LanguageSuggestions for AsyncGramchecker {
 
         let mut gramcheckers = self.gramcheckers.write();
 
-        let gramchecker = match gramcheckers.remove(language) {
+        let pool = match gramcheckers.remove(language) {
             Some(s) => s,
             None => {
-                return Box::new(err(ApiError {
-                    message: format!("No grammar checker available for language {}", &language),
-                }));
+                return Box::new(err(ApiError::LanguageNotFound(format!(
+                    "No grammar checker available for language {}",
+                    &language
+                ))));
             }
         };
 
         let cloned_gramcheckers = Arc::clone(&self.gramcheckers);
         let language = language.to_owned();
+        let deaths = join_all(
+            pool.executor
+                .members
+                .iter()
+                .map(|member| member.addr.send(Die))
+                .collect::<Vec<_>>(),
+        );
 
         Box::new(
-            gramchecker
-                .send(Die)
+            deaths
                 .map_err(move |err| {
-                    // Put the address back in since we failed to send the die message
+                    // Put the pool back in since we failed to send the die message
                     let mut cloned_gramcheckers = cloned_gramcheckers.write();
-                    cloned_gramcheckers.insert(language.clone(), gramchecker);
+                    cloned_gramcheckers.insert(language.clone(), pool);
 
-                    ApiError {
-                        message: format!(
-                            "Something failed in the message delivery process for language {}: {}",
-                            &language, err
-                        ),
-                    }
+                    ApiError::Unavailable(format!(
+                        "Something failed in the message delivery process for language {}: {}",
+                        &language, err
+                    ))
                 })
                 .and_then(|_| ok(())),
         )
     }
+
+    fn models(&self) -> Vec<ModelStatus> {
+        let gramcheckers = self.gramcheckers.read();
+
+        gramcheckers
+            .iter()
+            .map(|(language, pool)| pool.info.status(language, pool.executor.is_alive()))
+            .collect()
+    }
+}
+
+/// Looks up the error tags of the grammar checker a request for `language` would use
+pub fn preferences(
+    preferences: &HashMap<String, BTreeMap<String, String>>,
+    language: &str,
+) -> Result<GramcheckPreferencesResponse, ApiError> {
+    resolve(preferences.keys().map(String::as_str), language)
+        .and_then(|key| preferences.get_key_value(key))
+        .map(|(language, error_tags)| GramcheckPreferencesResponse {
+            language: language.to_owned(),
+            error_tags: error_tags.to_owned(),
+        })
+        .ok_or_else(|| {
+            ApiError::LanguageNotFound(format!(
+                "No grammar checker available for language {}",
+                language
+            ))
+        })
 }
 
 pub fn list_preferences(data_file_path: &str) -> Result<BTreeMap<String, String>, Error> {
@@ -284,8 +746,14 @@ pub fn list_preferences(data_file_path: &str) -> Result<BTreeMap<String, String>
         .arg("-a")
         .arg(data_file_path)
         .arg("-p")
-        .output()
-        .expect("failed to run divvun-checker");
+        .output()?;
+
+    if !process.status.success() {
+        return Err(to_io_err(format!(
+            "divvun-checker exited with {} listing the preferences of {}",
+            process.status, data_file_path
+        )));
+    }
 
     let regex = Regex::new(r"- \[.\] ([^\s+]+)\s+(.+)$").expect("valid regex");
     let toggle_separator = "==== Toggles: ====";
@@ -317,10 +785,155 @@ fn to_io_err(cause: impl ToString) -> Error {
 
 #[cfg(test)]
 mod test {
-    use serde_json::json;
+    use super::*;
+
+    /// An executor without a child, which is enough to hold an address to. Starting a new
+    /// child always fails, so that tests don't depend on `divvun-checker` being installed.
+    fn idle_executor() -> GramcheckExecutor {
+        GramcheckExecutor {
+            process: None,
+            path: "se.zcheck".to_owned(),
+            language: "se".to_owned(),
+            terminated: false,
+            timeout: Duration::from_secs(1),
+            restarts: Arc::new(AtomicUsize::new(0)),
+            consecutive_failures: 0,
+            quarantined_until: None,
+            spawn: |_| Err(to_io_err("No divvun-checker in tests")),
+        }
+    }
+
+    fn pool(in_flight: &[usize], queue_size: usize) -> GramcheckPool {
+        GramcheckPool {
+            members: in_flight
+                .iter()
+                .map(|&count| PoolMember {
+                    addr: idle_executor().start(),
+                    in_flight: Arc::new(AtomicUsize::new(count)),
+                })
+                .collect(),
+            queue_size,
+        }
+    }
+
+    fn counts(pool: &GramcheckPool) -> Vec<usize> {
+        pool.members
+            .iter()
+            .map(|member| member.in_flight.load(Ordering::SeqCst))
+            .collect()
+    }
+
+    #[test]
+    fn test_pool_size() {
+        let mut config = GramcheckPoolConfig {
+            default_size: 2,
+            ..GramcheckPoolConfig::default()
+        };
+        config.sizes.insert("se".to_owned(), 4);
+        config.sizes.insert("sma".to_owned(), 0);
+
+        assert_eq!(config.size_for("se"), 4);
+        assert_eq!(config.size_for("smj"), 2);
+        // A pool always has at least one member
+        assert_eq!(config.size_for("sma"), 1);
+    }
+
+    #[test]
+    fn test_pool_dispatches_to_least_busy() {
+        let _system = System::new("test");
+        let pool = pool(&[2, 0, 1], 10);
+
+        let (member, in_flight) = pool.reserve().unwrap();
+        assert!(Arc::ptr_eq(&member.in_flight, &pool.members[1].in_flight));
+        assert_eq!(counts(&pool), vec![2, 1, 1]);
+
+        let (member, _in_flight) = pool.reserve().unwrap();
+        assert!(Arc::ptr_eq(&member.in_flight, &pool.members[1].in_flight));
+        assert_eq!(counts(&pool), vec![2, 2, 1]);
+
+        drop(in_flight);
+        assert_eq!(counts(&pool), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn test_pool_rejects_over_queue_size() {
+        let _system = System::new("test");
+        let pool = pool(&[1, 1], 3);
+
+        let reserved = pool.reserve();
+        assert!(reserved.is_some());
+        assert!(pool.reserve().is_none());
+        assert_eq!(counts(&pool), vec![2, 1]);
+
+        // Dropping a request before it completes frees its place
+        drop(reserved);
+        assert_eq!(counts(&pool), vec![1, 1]);
+        assert!(pool.reserve().is_some());
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(MAX_CONSECUTIVE_FAILURES), BASE_BACKOFF);
+        assert_eq!(backoff(MAX_CONSECUTIVE_FAILURES + 1), BASE_BACKOFF * 2);
+        assert_eq!(backoff(MAX_CONSECUTIVE_FAILURES + 4), BASE_BACKOFF * 16);
+        assert_eq!(backoff(MAX_CONSECUTIVE_FAILURES + 8), BASE_BACKOFF * 256);
+
+        // The backoff stops growing at the maximum
+        assert_eq!(backoff(MAX_CONSECUTIVE_FAILURES + 9), MAX_BACKOFF);
+        assert_eq!(backoff(u32::max_value()), MAX_BACKOFF);
+    }
 
     #[test]
-    fn test_foo() {
-        let _some_data = json!({"errs":[["heno",0,4,"typo","Čállinmeattáhus",[]]],"text":"heno."});
+    fn test_quarantine_after_consecutive_failures() {
+        let mut executor = idle_executor();
+
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            executor.fail();
+            assert!(executor.check_quarantine().is_ok());
+        }
+
+        executor.fail();
+        assert_eq!(executor.consecutive_failures, MAX_CONSECUTIVE_FAILURES);
+        // No child is started once the language is quarantined
+        assert_eq!(
+            executor.restarts.load(Ordering::SeqCst),
+            MAX_CONSECUTIVE_FAILURES as usize - 1
+        );
+
+        match executor.check(GramcheckRequest::new("heno".to_owned())) {
+            Err(ApiError::Unavailable(_)) => {}
+            _ => panic!("Expected the language to be quarantined"),
+        }
+
+        // Requests refused during the quarantine are not failures of the child
+        assert_eq!(executor.consecutive_failures, MAX_CONSECUTIVE_FAILURES);
+
+        executor.quarantined_until = Some(Instant::now());
+        assert!(executor.check_quarantine().is_ok());
+        assert!(executor.quarantined_until.is_none());
+    }
+
+    #[test]
+    fn test_unparseable_answer_restarts_child() {
+        // A child that takes requests and whose answers are made up by the test
+        let child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let (tx, lines) = channel();
+        tx.send(Ok("{\"errs\": [\n".to_owned())).unwrap();
+
+        let mut executor = idle_executor();
+        executor.process = Some(CheckerProcess { child, lines });
+
+        match executor.check(GramcheckRequest::new("heno".to_owned())) {
+            Err(ApiError::BackendFailure(_)) => {}
+            _ => panic!("Expected the answer to fail to parse"),
+        }
+
+        // The child is replaced once, rather than being asked again out of sync
+        assert_eq!(executor.consecutive_failures, 1);
+        assert_eq!(executor.restarts.load(Ordering::SeqCst), 1);
     }
 }
@@ -1,55 +1,82 @@
-use actix_web::{web, HttpResponse};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
 
 use futures::future::{result, Future};
+use serde::Deserialize;
 
+use crate::metrics::track;
 use crate::server::state::State;
 
+use super::batch::{check_batch, BatchRequest, BatchResponse};
 use super::data_files::{
-    available_languages, AvailableLanguagesByType, AvailableLanguagesResponse, DataFileType,
+    available_languages, languages, speller_variants, AvailableLanguagesByType,
+    AvailableLanguagesResponse, DataFileType,
 };
-use super::grammar::{GramcheckPreferencesResponse, GramcheckRequest};
+use super::grammar::{preferences, GramcheckRequest};
 use super::hyphenation::HyphenationRequest;
-use super::speller::SpellerRequest;
+use super::session::CheckSession;
+use super::speller::{speller_info, SpellerRequest};
+use super::tags::parse_accept_language;
+
+#[derive(Deserialize)]
+pub struct LanguagesQuery {
+    pub ui: Option<String>,
+}
 
 pub fn get_available_languages_handler(
+    req: HttpRequest,
+    query: web::Query<LanguagesQuery>,
     state: web::Data<State>,
-) -> actix_web::Result<web::Json<AvailableLanguagesResponse>> {
-    let config = &state.config;
+) -> HttpResponse {
+    // `?ui=` takes precedence over the Accept-Language header
+    let ui_languages = match &query.ui {
+        Some(ui) => parse_accept_language(ui),
+        None => req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(parse_accept_language)
+            .unwrap_or_default(),
+    };
 
-    let grammar_checker_langs =
-        available_languages(config.data_file_dir.as_path(), DataFileType::Grammar);
-    let spell_checker_langs =
-        available_languages(config.data_file_dir.as_path(), DataFileType::Spelling);
-    let hyphenation_langs =
-        available_languages(config.data_file_dir.as_path(), DataFileType::Hyphenation);
+    let grammar_models = state.models(DataFileType::Grammar);
+    let speller_models = state.models(DataFileType::Spelling);
+    let hyphenation_models = state.models(DataFileType::Hyphenation);
 
-    Ok(web::Json(AvailableLanguagesResponse {
+    let response = AvailableLanguagesResponse {
         available: AvailableLanguagesByType {
-            grammar: grammar_checker_langs,
-            speller: spell_checker_langs,
-            hyphenation: hyphenation_langs,
+            grammar: available_languages(&grammar_models),
+            speller: available_languages(&speller_models),
+            hyphenation: available_languages(&hyphenation_models),
         },
-    }))
+        variants: speller_variants(speller_models.clone()),
+        languages: languages(
+            vec![
+                (DataFileType::Grammar, grammar_models),
+                (DataFileType::Spelling, speller_models),
+                (DataFileType::Hyphenation, hyphenation_models),
+            ],
+            &ui_languages,
+        ),
+    };
+
+    HttpResponse::Ok()
+        .header(header::VARY, "Accept-Language")
+        .json(response)
 }
 
 pub fn get_gramcheck_preferences_handler(
     path: web::Path<String>,
     state: web::Data<State>,
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
-    let prefs = &state.gramcheck_preferences;
-    let language = path;
-
-    let lock = prefs.read();
-    let error_tags = match lock.get(&*language) {
-        Some(s) => s,
-        None => {
-            return result(Ok(HttpResponse::InternalServerError().into()));
-        }
-    };
+    let response = preferences(&state.gramcheck_preferences.read(), &path);
 
-    result(Ok(HttpResponse::Ok().json(GramcheckPreferencesResponse {
-        error_tags: error_tags.to_owned(),
-    })))
+    result(
+        response
+            .map(|response| HttpResponse::Ok().json(response))
+            .map_err(actix_web::Error::from),
+    )
 }
 
 pub fn gramchecker_handler(
@@ -59,10 +86,15 @@ pub fn gramchecker_handler(
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
     let grammar_suggestions = &state.language_functions.grammar_suggestions;
 
-    grammar_suggestions
-        .suggestions(body.0, &path)
-        .from_err()
-        .map(|res| HttpResponse::Ok().json(res))
+    let model_key = grammar_suggestions.model_key(&body.0, &path);
+
+    track(
+        "grammar",
+        model_key,
+        grammar_suggestions.suggestions(body.0, &path),
+    )
+    .from_err()
+    .map(|res| HttpResponse::Ok().json(res))
 }
 
 pub fn hyphenation_handler(
@@ -72,21 +104,77 @@ pub fn hyphenation_handler(
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
     let hyphenation_suggestions = &state.language_functions.hyphenation_suggestions;
 
-    hyphenation_suggestions
-        .suggestions(body.0, &path)
-        .from_err()
-        .map(|res| HttpResponse::Ok().json(res))
+    let model_key = hyphenation_suggestions.model_key(&body.0, &path);
+
+    track(
+        "hyphenation",
+        model_key,
+        hyphenation_suggestions.suggestions(body.0, &path),
+    )
+    .from_err()
+    .map(|res| HttpResponse::Ok().json(res))
+}
+
+#[derive(Deserialize)]
+pub struct VariantQuery {
+    pub variant: Option<String>,
 }
 
 pub fn speller_handler(
     body: web::Json<SpellerRequest>,
     path: web::Path<String>,
+    query: web::Query<VariantQuery>,
     state: web::Data<State>,
 ) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
     let spelling_suggestions = &state.language_functions.spelling_suggestions;
 
-    spelling_suggestions
-        .suggestions(body.0, &path)
+    let mut request = body.into_inner();
+    if let Some(variant) = query.into_inner().variant {
+        request.variant = Some(variant);
+    }
+
+    let model_key = spelling_suggestions.model_key(&request, &path);
+
+    track(
+        "speller",
+        model_key,
+        spelling_suggestions.suggestions(request, &path),
+    )
+    .from_err()
+    .map(|res| HttpResponse::Ok().json(res))
+}
+
+pub fn speller_info_handler(
+    path: web::Path<String>,
+    query: web::Query<VariantQuery>,
+    state: web::Data<State>,
+) -> actix_web::Result<HttpResponse> {
+    let info = speller_info(
+        state.models(DataFileType::Spelling),
+        &path,
+        query.variant.as_ref().map(String::as_str),
+    )?;
+
+    Ok(HttpResponse::Ok().json(info))
+}
+
+pub fn batch_handler(
+    body: web::Json<BatchRequest>,
+    state: web::Data<State>,
+) -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
+    check_batch(&state, body.into_inner().inputs)
         .from_err()
-        .map(|res| HttpResponse::Ok().json(res))
+        .map(|results| {
+            HttpResponse::Ok().json(BatchResponse {
+                results: results.into_iter().collect(),
+            })
+        })
+}
+
+pub fn session_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<State>,
+) -> actix_web::Result<HttpResponse> {
+    ws::start(CheckSession::new(state.get_ref().clone()), &req, stream)
 }
@@ -1,23 +1,31 @@
-use std::io::Write;
-use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use actix::prelude::*;
-use futures::future::{err, ok, Future};
+use actix_web::web;
+use futures::future::{err, ok, Either, Future};
 use hashbrown::HashMap;
-use log::{info, warn};
+use log::{error, info, warn};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 
 use divvunspell::tokenizer::Tokenize;
 
 use crate::error::ApiError;
+use crate::language::data_files::DataFileType;
+use crate::language::lookup::{load_transducer, lookup, LookupTransducer};
+use crate::language::models::{LoadedModel, ModelInfo, ModelStatus};
+use crate::language::tags::resolve;
+use crate::language::text::{OffsetIndexer, TextOffsets};
+use crate::metrics::{count_restart, Queued, HYPHENATION_DURATION};
 use crate::server::state::{LanguageSuggestions, UnhoistFutureExt};
 
 pub struct HyphenationExecutor {
+    pub transducer: Arc<LookupTransducer>,
     pub path: String,
     pub language: String,
     pub terminated: bool,
+    pub restarts: Arc<AtomicUsize>,
 }
 
 impl Actor for HyphenationExecutor {
@@ -28,6 +36,8 @@ impl actix::Supervised for HyphenationExecutor {
     fn restarting(&mut self, _ctx: &mut Context<HyphenationExecutor>) {
         if !self.terminated {
             warn!("Hyphenation actor for {} died, restarting", &self.language);
+            self.restarts.fetch_add(1, Ordering::SeqCst);
+            count_restart(DataFileType::Hyphenation, &self.language);
         }
     }
 }
@@ -45,20 +55,23 @@ impl Handler<Die> for HyphenationExecutor {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct HyphenationRequest {
     pub text: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct HyphenationResponse {
     pub text: String,
+    /// Tag of the model that handled the request, which may differ from the requested one
+    pub language: String,
     pub results: Vec<HyphenationResult>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct HyphenationResult {
     pub word: String,
+    pub offsets: TextOffsets,
     pub hyphenations: Vec<HyphenationPattern>,
 }
 
@@ -76,69 +89,54 @@ impl Handler<HyphenationRequest> for HyphenationExecutor {
     type Result = Result<HyphenationResponse, ApiError>;
 
     fn handle(&mut self, msg: HyphenationRequest, _: &mut Self::Context) -> Self::Result {
-        let cloned_text = msg.text.clone();
-        let words = cloned_text.word_indices().map(|x| x.1).into_iter();
-
-        let results = words
-            .map(|word| {
-                let mut hfst_child = Command::new("hfst-lookup")
-                    .stdin(Stdio::piped())
-                    .stdout(Stdio::piped())
-                    .arg("-n")
-                    .arg("1")
-                    .arg("-q")
-                    .arg(&self.path)
-                    .spawn()?;
-
-                {
-                    let hfst_in = hfst_child.stdin.as_mut().unwrap();
-                    hfst_in.write_all(&word.as_bytes())?;
-                }
+        let timer = HYPHENATION_DURATION
+            .with_label_values(&[&self.language])
+            .start_timer();
 
-                let result = String::from_utf8(hfst_child.wait_with_output()?.stdout)?
-                    .trim()
-                    .to_string();
-
-                let hyphenations = result
-                    .lines()
-                    .map(|line| {
-                        let components: Vec<&str> = line.split("\t").collect();
-                        if components.len() < 3 {
-                            return Err(ApiError {
-                                message: format!("hfst-lookup returning unexpected number of tokens per word: {}", line),
-                            });
-                        }
-
-                        let weight = components[2].parse();
-                        if let Ok(weight) = weight {
-                            return Ok(HyphenationPattern {
-                                value: components[1].to_owned(),
-                                weight,
-                            });
-                        } else {
-                            return Err(ApiError {
-                                message: format!("Failed to parse float value from hfst-lookup: {}", line),
-                            });
-                        }
+        let cloned_text = msg.text.clone();
+        let words = cloned_text.word_indices().into_iter();
+        let mut indexer = OffsetIndexer::new(&cloned_text);
+
+        let results: Vec<HyphenationResult> = words
+            .map(|(index, word)| {
+                // Like `hfst-lookup -n 1`, only the best hyphenation of a word is returned
+                let mut hyphenations: Vec<HyphenationPattern> = lookup(&self.transducer, word)
+                    .into_iter()
+                    .take(1)
+                    .map(|(value, weight)| HyphenationPattern {
+                        value,
+                        weight: weight as f64,
                     })
-                    .collect::<Result<Vec<HyphenationPattern>, ApiError>>()?;
+                    .collect();
+
+                // Unknown words are echoed back the same way hfst-lookup reports them
+                if hyphenations.is_empty() {
+                    hyphenations.push(HyphenationPattern {
+                        value: format!("{}+?", word),
+                        weight: std::f64::INFINITY,
+                    });
+                }
 
-                Ok(HyphenationResult {
+                HyphenationResult {
                     word: word.to_owned(),
+                    offsets: indexer.offsets(index, word),
                     hyphenations,
-                })
+                }
             })
-            .collect::<Result<Vec<HyphenationResult>, ApiError>>()?;
+            .collect();
+
+        timer.observe_duration();
 
         Ok(HyphenationResponse {
             text: cloned_text,
+            language: self.language.clone(),
             results,
         })
     }
 }
 
 pub struct AsyncHyphenator {
-    pub hyphenators: Arc<RwLock<HashMap<String, Addr<HyphenationExecutor>>>>,
+    pub hyphenators: Arc<RwLock<HashMap<String, LoadedModel<Addr<HyphenationExecutor>>>>>,
 }
 
 impl LanguageSuggestions for AsyncHyphenator {
@@ -152,49 +150,95 @@ impl LanguageSuggestions for AsyncHyphenator {
     ) -> Box<dyn Future<Item = Self::Response, Error = ApiError>> {
         let lock = self.hyphenators.read();
 
-        let hyphenator = match lock.get(language) {
-            Some(s) => s,
+        let (language, hyphenator) = match resolve(lock.keys().map(String::as_str), language)
+            .and_then(|key| lock.get_key_value(key))
+        {
+            Some((key, s)) => (key.as_str(), s),
             None => {
-                return Box::new(err(ApiError {
-                    message: format!("No hyphenator available for language {}", &language),
-                }));
+                return Box::new(err(ApiError::LanguageNotFound(format!(
+                    "No hyphenator available for language {}",
+                    &language
+                ))));
             }
         };
 
+        let queued = Queued::new(DataFileType::Hyphenation, language);
+
         let language = language.to_owned();
 
         Box::new(
             hyphenator
+                .executor
                 .send(message)
-                .map_err(move |err| ApiError {
-                    message: format!(
+                .then(move |res| {
+                    drop(queued);
+                    res
+                })
+                .map_err(move |err| {
+                    ApiError::Unavailable(format!(
                         "Something failed in the message delivery process for language {}: {}",
                         &language, err
-                    ),
+                    ))
                 })
                 .unhoist(),
         )
     }
 
+    fn model_key(&self, _message: &Self::Request, language: &str) -> Option<String> {
+        let lock = self.hyphenators.read();
+        resolve(lock.keys().map(String::as_str), language).map(str::to_owned)
+    }
+
     fn add(&self, language: &str, path: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
         info!("Adding Hyphenator for {}", language);
 
-        let mut lock = self.hyphenators.write();
+        let hyphenators = Arc::clone(&self.hyphenators);
+        let language = language.to_owned();
+        let path = path.to_owned();
 
-        let hyphenator_path = path.to_owned();
+        // The transducer is read on the blocking thread pool before touching the running
+        // hyphenator, so that a bad file leaves the current one serving
+        let loaded = web::block(move || {
+            let transducer = load_transducer(&path).map_err(|message| {
+                error!("{}", message);
+                ApiError::Internal(message)
+            })?;
 
-        let owned_language = language.to_owned();
-        let hyphenator = actix::Supervisor::start_in_arbiter(&actix::Arbiter::new(), move |_| {
-            HyphenationExecutor {
-                path: hyphenator_path,
-                language: owned_language,
-                terminated: false,
+            Ok::<_, ApiError>((Arc::new(transducer), ModelInfo::new(&path), path))
+        })
+        .map_err(ApiError::from);
+
+        Box::new(loaded.and_then(move |(transducer, info, path)| {
+            let restarts = Arc::clone(&info.restarts);
+
+            let owned_language = language.clone();
+            let hyphenator =
+                actix::Supervisor::start_in_arbiter(&actix::Arbiter::new(), move |_| {
+                    HyphenationExecutor {
+                        transducer: Arc::clone(&transducer),
+                        path: path.clone(),
+                        language: owned_language,
+                        terminated: false,
+                        restarts,
+                    }
+                });
+
+            let previous = hyphenators.write().insert(
+                language,
+                LoadedModel {
+                    executor: hyphenator,
+                    info,
+                },
+            );
+
+            match previous {
+                // Requests already queued on the replaced hyphenator are answered before it stops
+                Some(previous) => {
+                    Either::A(previous.executor.send(Die).then(|_| ok::<(), ApiError>(())))
+                }
+                None => Either::B(ok(())),
             }
-        });
-
-        lock.insert(language.to_owned(), hyphenator);
-
-        Box::new(ok(()))
+        }))
     }
 
     fn remove(&self, language: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
@@ -205,9 +249,10 @@ impl LanguageSuggestions for AsyncHyphenator {
         let hyphenator = match lock.remove(language) {
             Some(s) => s,
             None => {
-                return Box::new(err(ApiError {
-                    message: format!("No hyphenator available for language {}", &language),
-                }));
+                return Box::new(err(ApiError::LanguageNotFound(format!(
+                    "No hyphenator available for language {}",
+                    &language
+                ))));
             }
         };
 
@@ -216,20 +261,31 @@ impl LanguageSuggestions for AsyncHyphenator {
 
         Box::new(
             hyphenator
+                .executor
                 .send(Die)
                 .map_err(move |err| {
                     // Put the address back in since we failed to send the die message
                     let mut lock = cloned_hyphenators.write();
                     lock.insert(language.clone(), hyphenator);
 
-                    ApiError {
-                        message: format!(
-                            "Something failed in the message delivery process for language {}: {}",
-                            &language, err
-                        ),
-                    }
+                    ApiError::Unavailable(format!(
+                        "Something failed in the message delivery process for language {}: {}",
+                        &language, err
+                    ))
                 })
                 .and_then(|_| ok(())),
         )
     }
+
+    fn models(&self) -> Vec<ModelStatus> {
+        let lock = self.hyphenators.read();
+
+        lock.iter()
+            .map(|(language, hyphenator)| {
+                hyphenator
+                    .info
+                    .status(language, hyphenator.executor.connected())
+            })
+            .collect()
+    }
 }
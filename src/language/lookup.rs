@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::Path;
+
+use divvunspell::transducer::{hfst::HfstTransducer, Transducer};
+use divvunspell::types::{
+    FlagDiacriticOperation, FlagDiacriticOperator, SymbolNumber, TransitionTableIndex, ValueNumber,
+    Weight,
+};
+use divvunspell::vfs::Fs;
+
+pub type LookupTransducer = HfstTransducer<File>;
+
+/// Upper bound on the search states explored for a single word, guarding against
+/// epsilon cycles in the transducer
+const MAX_LOOKUP_STATES: usize = 100_000;
+
+struct LookupState {
+    index: TransitionTableIndex,
+    input_pos: usize,
+    output: Vec<SymbolNumber>,
+    weight: Weight,
+    flags: Vec<ValueNumber>,
+}
+
+pub fn load_transducer(path: &str) -> Result<LookupTransducer, String> {
+    HfstTransducer::from_path(&Fs, Path::new(path))
+        .map_err(|e| format!("Failed to load transducer {}: {:?}", path, e))
+}
+
+/// Looks up `word` in an HFST optimized-lookup transducer, returning each distinct output string
+/// with the weight of its best path, best first. Flag diacritics are applied along the way and
+/// left out of the output.
+pub fn lookup(transducer: &LookupTransducer, word: &str) -> Vec<(String, Weight)> {
+    lookup_with_limit(transducer, word, MAX_LOOKUP_STATES)
+}
+
+fn lookup_with_limit(
+    transducer: &LookupTransducer,
+    word: &str,
+    max_states: usize,
+) -> Vec<(String, Weight)> {
+    let alphabet = transducer.alphabet();
+
+    let mut buf = [0u8; 4];
+    let input = match word
+        .chars()
+        .map(|c| {
+            alphabet
+                .string_to_symbol()
+                .get(&*c.encode_utf8(&mut buf))
+                .copied()
+        })
+        .collect::<Option<Vec<SymbolNumber>>>()
+    {
+        Some(input) => input,
+        None => return vec![],
+    };
+
+    let operations = alphabet.operations();
+    let key_table = alphabet.key_table();
+
+    let mut results: Vec<(String, Weight)> = vec![];
+    let mut stack = vec![LookupState {
+        index: 0,
+        input_pos: 0,
+        output: vec![],
+        weight: 0.0,
+        flags: vec![0; alphabet.state_size() as usize],
+    }];
+    let mut explored = 0;
+
+    while let Some(state) = stack.pop() {
+        explored += 1;
+        if explored > max_states {
+            break;
+        }
+
+        if state.input_pos == input.len() && transducer.is_final(state.index) {
+            let value = state
+                .output
+                .iter()
+                .map(|&sym| key_table[sym as usize].as_str())
+                .collect::<String>();
+            let weight = state.weight + transducer.final_weight(state.index).unwrap_or(0.0);
+            results.push((value, weight));
+        }
+
+        if transducer.has_epsilons_or_flags(state.index + 1) {
+            if let Some(mut next) = transducer.next(state.index, 0) {
+                while let Some(transition) = transducer.take_epsilons_and_flags(next) {
+                    let input_sym = transducer.transition_input_symbol(next).unwrap_or(0);
+                    let mut flags = state.flags.clone();
+
+                    let allowed = match operations.get(&input_sym) {
+                        Some(op) if input_sym != 0 => apply_flag(op, &mut flags),
+                        _ => true,
+                    };
+
+                    if let (true, Some(target)) = (allowed, transition.target()) {
+                        let mut output = state.output.clone();
+                        match transition.symbol() {
+                            Some(sym) if sym != 0 && !operations.contains_key(&sym) => {
+                                output.push(sym)
+                            }
+                            _ => {}
+                        }
+
+                        stack.push(LookupState {
+                            index: target,
+                            input_pos: state.input_pos,
+                            output,
+                            weight: state.weight + transition.weight().unwrap_or(0.0),
+                            flags,
+                        });
+                    }
+
+                    next += 1;
+                }
+            }
+        }
+
+        if let Some(&input_sym) = input.get(state.input_pos) {
+            if transducer.has_transitions(state.index + 1, Some(input_sym)) {
+                if let Some(mut next) = transducer.next(state.index, input_sym) {
+                    while let Some(transition) = transducer.take_non_epsilons(next, input_sym) {
+                        if let Some(target) = transition.target() {
+                            let mut output = state.output.clone();
+                            if let Some(sym) = transition.symbol() {
+                                output.push(sym);
+                            }
+
+                            stack.push(LookupState {
+                                index: target,
+                                input_pos: state.input_pos + 1,
+                                output,
+                                weight: state.weight + transition.weight().unwrap_or(0.0),
+                                flags: state.flags.clone(),
+                            });
+                        }
+
+                        next += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    // Keep only the best weighted path for each distinct output
+    let mut seen = HashSet::new();
+    results.retain(|(value, _)| seen.insert(value.clone()));
+
+    results
+}
+
+fn apply_flag(op: &FlagDiacriticOperation, flags: &mut [ValueNumber]) -> bool {
+    let feature = op.feature as usize;
+    let value = op.value;
+
+    match op.operation {
+        FlagDiacriticOperator::PositiveSet => {
+            flags[feature] = value;
+            true
+        }
+        FlagDiacriticOperator::NegativeSet => {
+            flags[feature] = -value;
+            true
+        }
+        FlagDiacriticOperator::Require => {
+            if value == 0 {
+                flags[feature] != 0
+            } else {
+                flags[feature] == value
+            }
+        }
+        FlagDiacriticOperator::Disallow => {
+            if value == 0 {
+                flags[feature] == 0
+            } else {
+                flags[feature] != value
+            }
+        }
+        FlagDiacriticOperator::Clear => {
+            flags[feature] = 0;
+            true
+        }
+        FlagDiacriticOperator::Unification => {
+            let current = flags[feature];
+            if current == 0 || current == value || (current < 0 && -current != value) {
+                flags[feature] = value;
+                true
+            } else {
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FlagDiacriticOperator::*;
+
+    const TRANSDUCER: &str = "tests/resources/data_files/hyphenation/se.hfstol";
+
+    fn values(results: Vec<(String, Weight)>) -> Vec<String> {
+        results.into_iter().map(|(value, _)| value).collect()
+    }
+
+    fn apply(
+        flags: &mut [ValueNumber],
+        operation: FlagDiacriticOperator,
+        value: ValueNumber,
+    ) -> bool {
+        let op = FlagDiacriticOperation {
+            operation,
+            feature: 0,
+            value,
+        };
+        apply_flag(&op, flags)
+    }
+
+    #[test]
+    fn test_lookup() {
+        let transducer = load_transducer(TRANSDUCER).unwrap();
+
+        let results = values(lookup(&transducer, "pákhat"));
+        assert_eq!(results.first().map(String::as_str), Some("pák^hat"));
+    }
+
+    #[test]
+    fn test_lookup_unknown_symbol() {
+        let transducer = load_transducer(TRANSDUCER).unwrap();
+
+        assert!(lookup(&transducer, "pákhat☃").is_empty());
+    }
+
+    #[test]
+    fn test_lookup_state_limit() {
+        let transducer = load_transducer(TRANSDUCER).unwrap();
+
+        // Only the start state is explored, which is not final for a non-empty word
+        assert!(lookup_with_limit(&transducer, "pákhat", 1).is_empty());
+        assert!(!lookup_with_limit(&transducer, "pákhat", MAX_LOOKUP_STATES).is_empty());
+    }
+
+    #[test]
+    fn test_flag_set_and_require() {
+        let mut flags = vec![0];
+
+        assert!(!apply(&mut flags, Require, 0));
+        assert!(apply(&mut flags, PositiveSet, 1));
+        assert!(apply(&mut flags, Require, 0));
+        assert!(apply(&mut flags, Require, 1));
+        assert!(!apply(&mut flags, Require, 2));
+
+        assert!(apply(&mut flags, NegativeSet, 1));
+        assert!(!apply(&mut flags, Require, 1));
+    }
+
+    #[test]
+    fn test_flag_disallow_and_clear() {
+        let mut flags = vec![0];
+
+        assert!(apply(&mut flags, Disallow, 0));
+        assert!(apply(&mut flags, PositiveSet, 1));
+        assert!(!apply(&mut flags, Disallow, 0));
+        assert!(!apply(&mut flags, Disallow, 1));
+        assert!(apply(&mut flags, Disallow, 2));
+
+        assert!(apply(&mut flags, Clear, 0));
+        assert_eq!(flags, vec![0]);
+    }
+
+    #[test]
+    fn test_flag_unification() {
+        let mut flags = vec![0];
+
+        assert!(apply(&mut flags, Unification, 1));
+        assert!(apply(&mut flags, Unification, 1));
+        assert!(!apply(&mut flags, Unification, 2));
+        assert_eq!(flags, vec![1]);
+
+        // A negatively set feature unifies with any other value
+        assert!(apply(&mut flags, NegativeSet, 1));
+        assert!(!apply(&mut flags, Unification, 1));
+        assert!(apply(&mut flags, Unification, 2));
+        assert_eq!(flags, vec![2]);
+    }
+}
@@ -1,5 +1,11 @@
+pub mod batch;
 pub mod data_files;
 pub mod grammar;
 pub mod handlers;
 pub mod hyphenation;
+pub mod lookup;
+pub mod models;
+pub mod session;
 pub mod speller;
+pub mod tags;
+pub mod text;
@@ -0,0 +1,124 @@
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::language::tags::fallback_chain;
+
+/// A running model along with what is known about the file it was loaded from
+#[derive(Clone)]
+pub struct LoadedModel<T> {
+    pub executor: T,
+    pub info: ModelInfo,
+}
+
+#[derive(Clone)]
+pub struct ModelInfo {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub loaded_at: SystemTime,
+    /// Number of times the executors serving this model were restarted
+    pub restarts: Arc<AtomicUsize>,
+    /// Metadata found in the model file itself, only speller archives have any
+    pub archive: Option<ArchiveInfo>,
+}
+
+impl ModelInfo {
+    pub fn new(path: &str) -> Self {
+        let metadata = fs::metadata(path).ok();
+
+        ModelInfo {
+            path: path.to_owned(),
+            size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: metadata.and_then(|m| m.modified().ok()),
+            loaded_at: SystemTime::now(),
+            restarts: Arc::new(AtomicUsize::new(0)),
+            archive: None,
+        }
+    }
+
+    pub fn status(&self, language: &str, alive: bool) -> ModelStatus {
+        ModelStatus {
+            language: language.to_owned(),
+            path: self.path.clone(),
+            size: self.size,
+            modified: self.modified.and_then(unix_timestamp),
+            loaded_at: unix_timestamp(self.loaded_at).unwrap_or(0),
+            restarts: self.restarts.load(Ordering::SeqCst),
+            alive,
+            archive: self.archive.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelStatus {
+    pub language: String,
+    pub path: String,
+    pub size: u64,
+    /// Modification time of the file in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Time the model was loaded in seconds since the Unix epoch
+    pub loaded_at: u64,
+    pub restarts: usize,
+    pub alive: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveInfo>,
+}
+
+/// The metadata of a speller archive, from its `index.xml`
+#[derive(Serialize, Clone, Debug)]
+pub struct ArchiveInfo {
+    pub locale: String,
+    pub titles: Vec<ArchiveTitle>,
+    pub description: String,
+    pub producer: String,
+    /// Version of the speller, if the archive states one
+    pub version: Option<String>,
+    pub acceptor: ArchiveTransducer,
+    pub errmodel: ArchiveTransducer,
+}
+
+/// One of the transducers in a speller archive
+#[derive(Serialize, Clone, Debug)]
+pub struct ArchiveTransducer {
+    /// Name of the transducer file in the archive
+    pub id: String,
+    /// Uncompressed size of the transducer in bytes
+    pub size: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ArchiveTitle {
+    /// Language the title is written in, absent for the archive's default title
+    pub lang: Option<String>,
+    pub value: String,
+}
+
+impl ArchiveInfo {
+    /// Picks the title written in the first of `ui_languages` that has one, falling back to the
+    /// default title and then to the first one
+    pub fn title(&self, ui_languages: &[String]) -> Option<&str> {
+        ui_languages
+            .iter()
+            .flat_map(|tag| fallback_chain(tag))
+            .find_map(|candidate| {
+                self.titles.iter().find(|title| {
+                    title
+                        .lang
+                        .as_ref()
+                        .map_or(false, |lang| lang.eq_ignore_ascii_case(&candidate))
+                })
+            })
+            .or_else(|| self.titles.iter().find(|title| title.lang.is_none()))
+            .or_else(|| self.titles.first())
+            .map(|title| title.value.as_str())
+    }
+}
+
+fn unix_timestamp(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
@@ -0,0 +1,679 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web_actors::ws;
+use futures::future::Future;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::language::grammar::{GramcheckErrResponse, GramcheckRequest, GramcheckResponse};
+use crate::language::speller::{SpellerOptions, SpellerRequest, SpellerResponse, SpellerResult};
+use crate::language::text::utf16_len;
+use crate::metrics::track;
+use crate::server::state::State;
+
+/// How often the client of a session is pinged at most, so that dropped connections are noticed
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A paragraph of a session's document. Paragraphs get a new id whenever an edit touches them,
+/// so results of checks that finish after a later edit can be told apart and dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paragraph {
+    pub id: u64,
+    pub text: String,
+}
+
+/// The paragraphs an edit replaced, starting at the paragraph with index `start`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Splice {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+/// The text of a session, split into paragraphs on `\n` like the grammar checker does
+#[derive(Debug)]
+pub struct Document {
+    paragraphs: Vec<Paragraph>,
+    next_id: u64,
+    /// Largest size of the text in bytes, which neither opening nor editing may go over
+    max_len: usize,
+    /// Number of edits applied since the document was opened
+    pub version: u64,
+}
+
+impl Document {
+    /// An empty document whose text may grow to `max_len` bytes
+    pub fn new(max_len: usize) -> Self {
+        Document {
+            paragraphs: vec![Paragraph {
+                id: 0,
+                text: String::new(),
+            }],
+            next_id: 0,
+            max_len,
+            version: 0,
+        }
+    }
+
+    /// Starts over with another text. Paragraph ids keep counting up, so that checks of the
+    /// previous text can't be mistaken for checks of the new one.
+    pub fn replace(&mut self, text: &str) -> Result<(), ApiError> {
+        self.check_len(text.len())?;
+
+        self.paragraphs = self.split(text);
+        self.version = 0;
+
+        Ok(())
+    }
+
+    /// Size of the text in bytes
+    fn len(&self) -> usize {
+        let breaks = self.paragraphs.len() - 1;
+        self.paragraphs.iter().map(|p| p.text.len()).sum::<usize>() + breaks
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), ApiError> {
+        if len > self.max_len {
+            return Err(ApiError::UnprocessableInput(format!(
+                "Documents may be at most {} bytes, this one would be {}",
+                self.max_len, len
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn paragraphs(&self) -> &[Paragraph] {
+        &self.paragraphs
+    }
+
+    /// The index and text of the paragraph with the given id, if no edit has replaced it
+    pub fn paragraph(&self, id: u64) -> Option<(usize, &str)> {
+        self.paragraphs
+            .iter()
+            .position(|paragraph| paragraph.id == id)
+            .map(|index| (index, self.paragraphs[index].text.as_str()))
+    }
+
+    pub fn text(&self) -> String {
+        let texts: Vec<&str> = self.paragraphs.iter().map(|p| p.text.as_str()).collect();
+        texts.join("\n")
+    }
+
+    /// Deletes `delete` code units at `offset` and inserts `insert` in their place. Offsets
+    /// count UTF-16 code units, as editors on the web do.
+    pub fn edit(&mut self, offset: usize, delete: usize, insert: &str) -> Result<Splice, ApiError> {
+        let end = offset
+            .checked_add(delete)
+            .ok_or_else(|| ApiError::UnprocessableInput("Invalid edit length".to_owned()))?;
+
+        let (first, first_start) = self.locate(offset)?;
+        let (last, _) = self.locate(end)?;
+
+        // Only the paragraphs the edit touches are rebuilt
+        let mut text = self.paragraphs[first..=last]
+            .iter()
+            .map(|paragraph| paragraph.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let start_byte = byte_index(&text, offset - first_start)?;
+        let end_byte = byte_index(&text, end - first_start)?;
+        self.check_len(self.len() - (end_byte - start_byte) + insert.len())?;
+        text.replace_range(start_byte..end_byte, insert);
+
+        let paragraphs = self.split(&text);
+        let splice = Splice {
+            start: first,
+            removed: last - first + 1,
+            inserted: paragraphs.len(),
+        };
+
+        self.paragraphs.splice(first..=last, paragraphs);
+        self.version += 1;
+
+        Ok(splice)
+    }
+
+    fn split(&mut self, text: &str) -> Vec<Paragraph> {
+        text.split('\n')
+            .map(|text| {
+                self.next_id += 1;
+                Paragraph {
+                    id: self.next_id,
+                    text: text.to_owned(),
+                }
+            })
+            .collect()
+    }
+
+    /// The index and start offset of the paragraph an offset falls in. An offset at the end of
+    /// a paragraph, just before its line break, belongs to that paragraph.
+    fn locate(&self, offset: usize) -> Result<(usize, usize), ApiError> {
+        let mut start = 0;
+
+        for (index, paragraph) in self.paragraphs.iter().enumerate() {
+            let end = start + utf16_len(&paragraph.text);
+
+            if offset <= end {
+                return Ok((index, start));
+            }
+
+            start = end + 1;
+        }
+
+        Err(ApiError::UnprocessableInput(format!(
+            "Offset {} is past the end of the document",
+            offset
+        )))
+    }
+}
+
+/// Converts an offset in UTF-16 code units into a byte index into `text`
+fn byte_index(text: &str, offset: usize) -> Result<usize, ApiError> {
+    let mut units = 0;
+
+    for (index, c) in text.char_indices() {
+        if units == offset {
+            return Ok(index);
+        }
+
+        units += c.len_utf16();
+
+        if units > offset {
+            return Err(ApiError::UnprocessableInput(format!(
+                "Offset {} splits a surrogate pair",
+                offset
+            )));
+        }
+    }
+
+    Ok(text.len())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Starts checking a document, replacing the one the session had
+    Open(OpenSession),
+    Edit {
+        offset: usize,
+        delete: usize,
+        #[serde(default)]
+        insert: String,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpenSession {
+    pub language: String,
+    #[serde(default)]
+    pub text: String,
+    /// Speller variant to use instead of the default
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub ignore_tags: Vec<String>,
+    #[serde(default)]
+    pub enable_tags: Vec<String>,
+    #[serde(flatten)]
+    pub options: SpellerOptions,
+}
+
+/// Results are sent per paragraph, with offsets relative to the start of the paragraph and
+/// paragraph indices as of the document `version` they carry
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Opened {
+        version: u64,
+        paragraphs: usize,
+    },
+    /// Acknowledges an edit. Diagnostics of the removed paragraphs no longer apply.
+    Edited {
+        version: u64,
+        start: usize,
+        removed: usize,
+        inserted: usize,
+    },
+    Speller {
+        version: u64,
+        paragraph: usize,
+        language: String,
+        results: Vec<SpellerResult>,
+    },
+    Grammar {
+        version: u64,
+        paragraph: usize,
+        language: String,
+        errs: Vec<GramcheckErrResponse>,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        check: Option<&'static str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        paragraph: Option<usize>,
+        code: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CheckType {
+    Speller,
+    Grammar,
+}
+
+impl CheckType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CheckType::Speller => "speller",
+            CheckType::Grammar => "grammar",
+        }
+    }
+}
+
+enum CheckOutcome {
+    Speller(Result<SpellerResponse, ApiError>),
+    Grammar(Result<GramcheckResponse, ApiError>),
+}
+
+/// A WebSocket session that keeps a document and checks the paragraphs that edits change
+pub struct CheckSession {
+    state: State,
+    open: Option<OpenSession>,
+    document: Document,
+    /// Checks that are enabled, until they turn out to have no model for the language
+    checks: Vec<CheckType>,
+    queue: VecDeque<(u64, CheckType)>,
+    in_flight: usize,
+    /// When the client last sent a message or answered a ping
+    last_seen: Instant,
+}
+
+impl CheckSession {
+    pub fn new(state: State) -> Self {
+        CheckSession {
+            open: None,
+            document: Document::new(state.config.max_body_bytes),
+            checks: vec![],
+            queue: VecDeque::new(),
+            in_flight: 0,
+            last_seen: Instant::now(),
+            state,
+        }
+    }
+
+    fn receive(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = ApiError::InvalidInput(e.to_string());
+                return self.send_error(None, None, &error, ctx);
+            }
+        };
+
+        match message {
+            ClientMessage::Open(open) => {
+                if let Err(e) = self.document.replace(&open.text) {
+                    return self.send_error(None, None, &e, ctx);
+                }
+
+                self.open = Some(open);
+                self.checks = vec![CheckType::Speller, CheckType::Grammar];
+                self.queue.clear();
+
+                self.send(
+                    &ServerMessage::Opened {
+                        version: self.document.version,
+                        paragraphs: self.document.paragraphs().len(),
+                    },
+                    ctx,
+                );
+
+                let ids: Vec<u64> = self.document.paragraphs().iter().map(|p| p.id).collect();
+                self.enqueue(&ids, ctx);
+            }
+            ClientMessage::Edit {
+                offset,
+                delete,
+                insert,
+            } => {
+                if self.open.is_none() {
+                    let error = ApiError::InvalidInput("No document is open".to_owned());
+                    return self.send_error(None, None, &error, ctx);
+                }
+
+                let splice = match self.document.edit(offset, delete, &insert) {
+                    Ok(splice) => splice,
+                    Err(e) => return self.send_error(None, None, &e, ctx),
+                };
+
+                self.send(
+                    &ServerMessage::Edited {
+                        version: self.document.version,
+                        start: splice.start,
+                        removed: splice.removed,
+                        inserted: splice.inserted,
+                    },
+                    ctx,
+                );
+
+                let ids: Vec<u64> = self.document.paragraphs()
+                    [splice.start..splice.start + splice.inserted]
+                    .iter()
+                    .map(|p| p.id)
+                    .collect();
+                self.enqueue(&ids, ctx);
+            }
+        }
+    }
+
+    fn enqueue(&mut self, ids: &[u64], ctx: &mut ws::WebsocketContext<Self>) {
+        for &id in ids {
+            for &check in &self.checks {
+                self.queue.push_back((id, check));
+            }
+        }
+
+        self.pump(ctx);
+    }
+
+    /// Starts queued checks while fewer than `session_concurrency` are running, skipping those
+    /// of paragraphs that have been edited since they were queued
+    fn pump(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        while self.in_flight < self.state.config.session_concurrency {
+            let (id, check) = match self.queue.pop_front() {
+                Some(next) => next,
+                None => return,
+            };
+
+            if !self.checks.contains(&check) {
+                continue;
+            }
+
+            let (open, text) = match (&self.open, self.document.paragraph(id)) {
+                (Some(open), Some((_, text))) => (open, text.to_owned()),
+                _ => continue,
+            };
+
+            let outcome: Box<dyn Future<Item = CheckOutcome, Error = ()>> = match check {
+                CheckType::Speller => Box::new(
+                    self.check_spelling(open, text)
+                        .then(|result| Ok::<_, ()>(CheckOutcome::Speller(result))),
+                ),
+                CheckType::Grammar => Box::new(
+                    self.check_grammar(open, text)
+                        .then(|result| Ok::<_, ()>(CheckOutcome::Grammar(result))),
+                ),
+            };
+
+            self.in_flight += 1;
+
+            // Checks still running when the session closes are dropped along with it, which
+            // also takes them off the in-flight counts of the pools
+            ctx.spawn(outcome.into_actor(self).then(move |outcome, act, ctx| {
+                act.in_flight -= 1;
+
+                if let Ok(outcome) = outcome {
+                    act.report(id, outcome, ctx);
+                }
+
+                act.pump(ctx);
+                fut::ok(())
+            }));
+        }
+    }
+
+    fn check_spelling(
+        &self,
+        open: &OpenSession,
+        text: String,
+    ) -> impl Future<Item = SpellerResponse, Error = ApiError> {
+        // Only misspelled words are diagnostics
+        let request = SpellerRequest {
+            suggest_correct: false,
+            include_correct: false,
+            variant: open.variant.clone(),
+            options: open.options.clone(),
+            ..SpellerRequest::new(text)
+        };
+
+        let spelling_suggestions = &self.state.language_functions.spelling_suggestions;
+        let model_key = spelling_suggestions.model_key(&request, &open.language);
+
+        track(
+            "session_speller",
+            model_key,
+            spelling_suggestions.suggestions(request, &open.language),
+        )
+    }
+
+    fn check_grammar(
+        &self,
+        open: &OpenSession,
+        text: String,
+    ) -> impl Future<Item = GramcheckResponse, Error = ApiError> {
+        let request = GramcheckRequest {
+            ignore_tags: open.ignore_tags.clone(),
+            enable_tags: open.enable_tags.clone(),
+            ..GramcheckRequest::new(text)
+        };
+
+        let grammar_suggestions = &self.state.language_functions.grammar_suggestions;
+        let model_key = grammar_suggestions.model_key(&request, &open.language);
+
+        track(
+            "session_grammar",
+            model_key,
+            grammar_suggestions.suggestions(request, &open.language),
+        )
+    }
+
+    fn report(&mut self, id: u64, outcome: CheckOutcome, ctx: &mut ws::WebsocketContext<Self>) {
+        // The paragraph was edited while it was being checked, and is checked again
+        let index = match self.document.paragraph(id) {
+            Some((index, _)) => index,
+            None => return,
+        };
+
+        let version = self.document.version;
+
+        let (check, error) = match outcome {
+            CheckOutcome::Speller(Ok(response)) => {
+                return self.send(
+                    &ServerMessage::Speller {
+                        version,
+                        paragraph: index,
+                        language: response.language,
+                        results: response.results,
+                    },
+                    ctx,
+                )
+            }
+            CheckOutcome::Grammar(Ok(response)) => {
+                return self.send(
+                    &ServerMessage::Grammar {
+                        version,
+                        paragraph: index,
+                        language: response.language,
+                        errs: response.errs,
+                    },
+                    ctx,
+                )
+            }
+            CheckOutcome::Speller(Err(e)) => (CheckType::Speller, e),
+            CheckOutcome::Grammar(Err(e)) => (CheckType::Grammar, e),
+        };
+
+        // Without a model for the language the check is dropped for the rest of the session,
+        // and reported once rather than for every paragraph
+        if let ApiError::LanguageNotFound(_) = error {
+            if self.checks.contains(&check) {
+                self.checks.retain(|enabled| *enabled != check);
+                self.send_error(Some(check), None, &error, ctx);
+            }
+            return;
+        }
+
+        self.send_error(Some(check), Some(index), &error, ctx);
+    }
+
+    fn send(&self, message: &ServerMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::to_string(message) {
+            Ok(text) => ctx.text(text),
+            Err(e) => warn!("Failed to serialize session message: {}", e),
+        }
+    }
+
+    fn send_error(
+        &self,
+        check: Option<CheckType>,
+        paragraph: Option<usize>,
+        error: &ApiError,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.send(
+            &ServerMessage::Error {
+                check: check.map(|check| check.as_str()),
+                paragraph,
+                code: error.code().to_owned(),
+                message: error.message().to_owned(),
+            },
+            ctx,
+        );
+    }
+}
+
+impl Actor for CheckSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    /// Pings the client and closes the session once it has gone `session_idle_timeout_ms`
+    /// without a message or a pong
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let timeout = Duration::from_millis(self.state.config.session_idle_timeout_ms);
+        let interval = std::cmp::min(HEARTBEAT_INTERVAL, timeout / 2);
+
+        ctx.run_interval(interval, move |act, ctx| {
+            if act.last_seen.elapsed() > timeout {
+                debug!("Closing idle check session");
+                ctx.stop();
+                return;
+            }
+
+            ctx.ping("");
+        });
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for CheckSession {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        self.last_seen = Instant::now();
+
+        match msg {
+            ws::Message::Ping(msg) => ctx.pong(&msg),
+            ws::Message::Text(text) => self.receive(&text, ctx),
+            ws::Message::Binary(_) => {
+                let error = ApiError::InvalidInput("Messages must be JSON text".to_owned());
+                self.send_error(None, None, &error, ctx);
+            }
+            ws::Message::Close(_) => {
+                debug!("Check session closed");
+                ctx.stop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::new(1024);
+        document.replace(text).unwrap();
+        document
+    }
+
+    #[test]
+    fn test_document_edits() {
+        let mut document = document("Mun lean\nbuorre");
+        let ids: Vec<u64> = document.paragraphs().iter().map(|p| p.id).collect();
+
+        // Within a paragraph, only that paragraph is replaced
+        let splice = document.edit(4, 4, "ledjen").unwrap();
+        assert_eq!(
+            splice,
+            Splice {
+                start: 0,
+                removed: 1,
+                inserted: 1
+            }
+        );
+        assert_eq!(document.text(), "Mun ledjen\nbuorre");
+        assert_eq!(document.paragraphs()[1].id, ids[1]);
+        assert_ne!(document.paragraphs()[0].id, ids[0]);
+
+        // A line break splits a paragraph
+        let splice = document.edit(10, 0, "\nja").unwrap();
+        assert_eq!(
+            splice,
+            Splice {
+                start: 0,
+                removed: 1,
+                inserted: 2
+            }
+        );
+        assert_eq!(document.text(), "Mun ledjen\nja\nbuorre");
+
+        // Deleting a line break merges paragraphs
+        let splice = document.edit(13, 1, " ").unwrap();
+        assert_eq!(
+            splice,
+            Splice {
+                start: 1,
+                removed: 2,
+                inserted: 1
+            }
+        );
+        assert_eq!(document.text(), "Mun ledjen\nja buorre");
+        assert_eq!(document.version, 3);
+
+        assert!(document.edit(21, 0, "!").is_err());
+    }
+
+    #[test]
+    fn test_document_utf16_offsets() {
+        let mut document = document("𝄞 ođas");
+
+        document.edit(3, 0, "ja ").unwrap();
+        assert_eq!(document.text(), "𝄞 ja ođas");
+
+        assert!(document.edit(1, 0, "x").is_err());
+    }
+
+    #[test]
+    fn test_document_max_len() {
+        let mut document = Document::new(16);
+
+        assert!(document.replace("Mun lean buorre, giitu").is_err());
+        document.replace("Mun lean\nbuorre").unwrap();
+        assert_eq!(document.len(), 15);
+
+        // Replacing text with text of the same size is allowed at the limit
+        document.edit(14, 1, "!").unwrap();
+        document.edit(15, 0, "?").unwrap();
+        assert_eq!(document.len(), 16);
+
+        // An edit that would go over the limit is refused and leaves the document as it was
+        assert!(document.edit(16, 0, "!").is_err());
+        assert!(document.edit(0, 3, "Mii").is_ok());
+        assert!(document.edit(0, 3, "Moai").is_err());
+        assert_eq!(document.text(), "Mii lean\nbuorr!?");
+        assert_eq!(document.version, 3);
+    }
+}
@@ -1,22 +1,39 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
-use divvunspell::archive::{zip::ZipSpellerArchive, SpellerArchive};
-use futures::future::{err, ok, Future};
+use actix_web::web;
+use divvunspell::archive::{self, SpellerArchive};
+use futures::future::{err, ok, Either, Future};
 use hashbrown::HashMap;
-use log::{info, warn};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
 use parking_lot::RwLock;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
 
 use crate::error::ApiError;
+use crate::language::data_files::{default_variant, find_variant, split_variant, DataFileType};
+use crate::language::models::{
+    ArchiveInfo, ArchiveTitle, ArchiveTransducer, LoadedModel, ModelInfo, ModelStatus,
+};
+use crate::language::tags::{resolve, resolve_language};
+use crate::language::text::{OffsetIndexer, TextOffsets};
+use crate::metrics::{count_restart, Queued};
 use crate::server::state::{LanguageSuggestions, UnhoistFutureExt};
 use divvunspell::speller::suggestion::Suggestion;
+use divvunspell::speller::SpellerConfig;
 use divvunspell::tokenizer::Tokenize;
 
 pub struct DivvunSpellExecutor {
     pub speller_archive: Arc<dyn SpellerArchive>,
     pub language: String,
     pub terminated: bool,
+    pub restarts: Arc<AtomicUsize>,
 }
 
 impl Actor for DivvunSpellExecutor {
@@ -27,24 +44,117 @@ impl actix::Supervised for DivvunSpellExecutor {
     fn restarting(&mut self, _ctx: &mut Context<DivvunSpellExecutor>) {
         if !self.terminated {
             warn!("Actor for {} died, restarting", &self.language);
+            self.restarts.fetch_add(1, Ordering::SeqCst);
+            count_restart(DataFileType::Spelling, &self.language);
         }
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct SpellerRequest {
     pub text: String,
+    /// Whether to generate suggestions for words that are already spelled correctly
+    #[serde(default = "default_true")]
+    pub suggest_correct: bool,
+    /// Whether correctly spelled words are listed in the results at all
+    #[serde(default = "default_true")]
+    pub include_correct: bool,
+    /// Speller variant to use, such as `school` for `se-x-school.zhfst`, instead of the default
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(flatten)]
+    pub options: SpellerOptions,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+impl SpellerRequest {
+    pub fn new(text: String) -> Self {
+        SpellerRequest {
+            text,
+            suggest_correct: true,
+            include_correct: true,
+            variant: None,
+            options: SpellerOptions::default(),
+        }
+    }
+}
+
+/// Per-request overrides for the divvunspell `SpellerConfig`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SpellerOptions {
+    /// Maximum number of suggestions returned per word
+    pub max_suggestions: Option<usize>,
+    /// Suggestions weighted above this value are discarded
+    pub max_weight: Option<f32>,
+    /// Suggestions weighted further than this from the best one are discarded
+    pub beam: Option<f32>,
+    /// Whether to retry lookups with the case of the word altered
+    pub recase: Option<bool>,
+    /// Time budget for generating suggestions for the whole text. Once it runs out, the
+    /// remaining words are only checked for correctness. The budget is checked between words,
+    /// so it is best-effort: generating the suggestions of a single word is not interrupted,
+    /// and may go past it.
+    pub timeout_ms: Option<u64>,
+}
+
+impl SpellerOptions {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let invalid =
+            |field: &str| ApiError::UnprocessableInput(format!("Invalid value for {}", field));
+
+        if self.max_suggestions == Some(0) {
+            return Err(invalid("max_suggestions"));
+        }
+
+        if self.max_weight.map_or(false, |w| !w.is_finite() || w < 0.0) {
+            return Err(invalid("max_weight"));
+        }
+
+        if self.beam.map_or(false, |b| !b.is_finite() || b < 0.0) {
+            return Err(invalid("beam"));
+        }
+
+        Ok(())
+    }
+
+    pub fn speller_config(&self) -> SpellerConfig {
+        let mut config = SpellerConfig::default();
+
+        if let Some(max_suggestions) = self.max_suggestions {
+            config.n_best = Some(max_suggestions);
+        }
+
+        if let Some(max_weight) = self.max_weight {
+            config.max_weight = Some(max_weight);
+        }
+
+        if let Some(beam) = self.beam {
+            config.beam = Some(beam);
+        }
+
+        if let Some(recase) = self.recase {
+            config.recase = recase;
+        }
+
+        config
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct SpellerResponse {
     pub text: String,
+    /// Tag of the model that handled the request, which may differ from the requested one
+    pub language: String,
     pub results: Vec<SpellerResult>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct SpellerResult {
     pub word: String,
+    pub offsets: TextOffsets,
     pub is_correct: bool,
     pub suggestions: Vec<Suggestion>,
 }
@@ -57,29 +167,48 @@ impl Handler<SpellerRequest> for DivvunSpellExecutor {
     type Result = Result<SpellerResponse, ApiError>;
 
     fn handle(&mut self, msg: SpellerRequest, _: &mut Self::Context) -> Self::Result {
+        msg.options.validate()?;
+
         let speller = self.speller_archive.speller();
+        let config = msg.options.speller_config();
+        let deadline = msg
+            .options
+            .timeout_ms
+            .map(|ms| Instant::now() + Duration::from_millis(ms));
 
         let cloned_text = msg.text.clone();
-        let words = cloned_text.word_indices().map(|x| x.1).into_iter();
-        // let words = cloned_text.words().into_iter();
+        let words = cloned_text.word_indices().into_iter();
+        let mut indexer = OffsetIndexer::new(&cloned_text);
 
         let results: Vec<SpellerResult> = words
-            .map(|word| {
-                let cloned_speller = self.speller_archive.speller().clone();
-                let is_correct = Arc::clone(&speller).is_correct(word);
+            .filter_map(|(index, word)| {
+                let is_correct = Arc::clone(&speller).is_correct_with_config(word, &config);
+
+                if is_correct && !msg.include_correct {
+                    return None;
+                }
 
-                let suggestions = cloned_speller.suggest(word).into_iter().collect();
+                // divvunspell can't be interrupted, so the deadline is only checked between words
+                let timed_out = deadline.map_or(false, |deadline| Instant::now() >= deadline);
 
-                SpellerResult {
+                let suggestions = if timed_out || (is_correct && !msg.suggest_correct) {
+                    vec![]
+                } else {
+                    Arc::clone(&speller).suggest_with_config(word, &config)
+                };
+
+                Some(SpellerResult {
                     word: word.to_owned(),
+                    offsets: indexer.offsets(index, word),
                     is_correct,
                     suggestions,
-                }
+                })
             })
             .collect();
 
         Ok(SpellerResponse {
             text: cloned_text.clone(),
+            language: self.language.clone(),
             results,
         })
     }
@@ -98,8 +227,134 @@ impl Handler<Die> for DivvunSpellExecutor {
     }
 }
 
+fn archive_info(path: &str, archive: &dyn SpellerArchive) -> Option<ArchiveInfo> {
+    let metadata = archive.metadata()?;
+
+    // divvunspell leaves the version and the sizes of the transducers out, so they are read
+    // from the archive directly. This only works for zip archives, of box archives only the
+    // metadata itself is known.
+    let mut zip = File::open(path)
+        .ok()
+        .and_then(|file| ZipArchive::new(file).ok());
+    let mut entry_size = |name: &str| {
+        zip.as_mut()
+            .and_then(|zip| zip.by_name(name).ok().map(|entry| entry.size()))
+    };
+
+    let acceptor = ArchiveTransducer {
+        id: metadata.acceptor.id.clone(),
+        size: entry_size(&metadata.acceptor.id),
+    };
+    let errmodel = ArchiveTransducer {
+        id: metadata.errmodel.id.clone(),
+        size: entry_size(&metadata.errmodel.id),
+    };
+
+    Some(ArchiveInfo {
+        locale: metadata.info.locale.clone(),
+        titles: metadata
+            .info
+            .title
+            .iter()
+            .map(|title| ArchiveTitle {
+                lang: title.lang.clone(),
+                value: title.value.clone(),
+            })
+            .collect(),
+        description: metadata.info.description.clone(),
+        producer: metadata.info.producer.clone(),
+        version: zip.as_mut().and_then(archive_version),
+        acceptor,
+        errmodel,
+    })
+}
+
+/// Reads the `<version>` of the `<info>` in the archive's `index.xml`
+fn archive_version(zip: &mut ZipArchive<File>) -> Option<String> {
+    lazy_static! {
+        static ref VERSION: Regex =
+            Regex::new(r"(?s)<info>.*?<version[^>]*>([^<]*)</version>").unwrap();
+    }
+
+    let mut index = String::new();
+    zip.by_name("index.xml")
+        .ok()?
+        .read_to_string(&mut index)
+        .ok()?;
+
+    VERSION
+        .captures(&index)
+        .map(|captures| captures[1].trim().to_owned())
+        .filter(|version| !version.is_empty())
+}
+
+/// Picks the loaded speller for a requested tag and, optionally, variant
+fn find_speller<'a>(keys: &[&'a str], language: &str, variant: Option<&str>) -> Option<&'a str> {
+    // Variants are chosen among the models of the language the tag resolves to
+    match variant {
+        Some(variant) => resolve_language(keys.iter().cloned(), language)
+            .and_then(|base| find_variant(keys.iter().cloned(), base, variant)),
+        None => resolve(keys.iter().cloned(), language).or_else(|| {
+            resolve_language(keys.iter().cloned(), language)
+                .and_then(|base| default_variant(keys.iter().cloned(), base))
+        }),
+    }
+}
+
+fn speller_not_found(language: &str, variant: Option<&str>) -> ApiError {
+    ApiError::LanguageNotFound(match variant {
+        Some(variant) => format!(
+            "No speller variant {} available for language {}",
+            variant, language
+        ),
+        None => format!("No speller available for language {}", language),
+    })
+}
+
+/// What is known about a loaded speller and the archive it was loaded from
+#[derive(Serialize, Clone, Debug)]
+pub struct SpellerInfo {
+    /// Tag of the model the request resolved to
+    pub language: String,
+    pub variant: Option<String>,
+    pub size: u64,
+    /// Modification time of the file in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Time the model was loaded in seconds since the Unix epoch
+    pub loaded_at: u64,
+    /// Absent if the archive has no metadata
+    pub archive: Option<ArchiveInfo>,
+}
+
+/// Describes the speller that a request for `language` and `variant` would be served by
+pub fn speller_info(
+    models: Vec<ModelStatus>,
+    language: &str,
+    variant: Option<&str>,
+) -> Result<SpellerInfo, ApiError> {
+    let keys: Vec<&str> = models.iter().map(|model| model.language.as_str()).collect();
+    let key = find_speller(&keys, language, variant).map(str::to_owned);
+
+    let model = match models
+        .into_iter()
+        .find(|model| Some(&model.language) == key.as_ref())
+    {
+        Some(model) => model,
+        None => return Err(speller_not_found(language, variant)),
+    };
+
+    Ok(SpellerInfo {
+        variant: split_variant(&model.language).1.map(str::to_owned),
+        language: model.language,
+        size: model.size,
+        modified: model.modified,
+        loaded_at: model.loaded_at,
+        archive: model.archive,
+    })
+}
+
 pub struct AsyncSpeller {
-    pub spellers: Arc<RwLock<HashMap<String, Addr<DivvunSpellExecutor>>>>,
+    pub spellers: Arc<RwLock<HashMap<String, LoadedModel<Addr<DivvunSpellExecutor>>>>>,
 }
 
 impl LanguageSuggestions for AsyncSpeller {
@@ -112,53 +367,99 @@ impl LanguageSuggestions for AsyncSpeller {
         language: &str,
     ) -> Box<dyn Future<Item = Self::Response, Error = ApiError>> {
         let lock = self.spellers.read();
+        let keys: Vec<&str> = lock.keys().map(String::as_str).collect();
+        let variant = message.variant.as_ref().map(String::as_str);
 
-        let speller = match lock.get(language) {
-            Some(s) => s,
-            None => {
-                return Box::new(err(ApiError {
-                    message: format!("No speller available for language {}", &language),
-                }));
-            }
-        };
+        let (key, speller) =
+            match find_speller(&keys, language, variant).and_then(|key| lock.get_key_value(key)) {
+                Some(s) => s,
+                None => return Box::new(err(speller_not_found(language, variant))),
+            };
 
-        let language = language.to_owned();
+        let queued = Queued::new(DataFileType::Spelling, key);
+
+        let language = key.to_owned();
 
         Box::new(
             speller
+                .executor
                 .send(message)
-                .map_err(move |err| ApiError {
-                    message: format!(
+                .then(move |res| {
+                    drop(queued);
+                    res
+                })
+                .map_err(move |err| {
+                    ApiError::Unavailable(format!(
                         "Something failed in the message delivery process for language {}: {}",
                         &language, err
-                    ),
+                    ))
                 })
                 .unhoist(),
         )
     }
 
+    fn model_key(&self, message: &Self::Request, language: &str) -> Option<String> {
+        let lock = self.spellers.read();
+        let keys: Vec<&str> = lock.keys().map(String::as_str).collect();
+        let variant = message.variant.as_ref().map(String::as_str);
+
+        find_speller(&keys, language, variant).map(str::to_owned)
+    }
+
     fn add(&self, language: &str, path: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
         info!("Adding Speller for {}", language);
 
-        let mut lock = self.spellers.write();
-
-        let speller_path = path.to_owned();
-
-        let owned_language = language.to_owned();
-        let speller = actix::Supervisor::start_in_arbiter(&actix::Arbiter::new(), move |_| {
-            let ar = ZipSpellerArchive::open(&std::path::Path::new(&speller_path))
-                .map(|x| Arc::new(x) as _)
-                .unwrap();
-            DivvunSpellExecutor {
-                speller_archive: ar,
-                language: owned_language,
-                terminated: false,
+        let spellers = Arc::clone(&self.spellers);
+        let language = language.to_owned();
+        let path = path.to_owned();
+
+        // The archive is opened on the blocking thread pool before touching the running
+        // speller, so that a bad file leaves the current one serving
+        let opened = web::block(move || {
+            // divvunspell picks the archive type, zip or memory mapped box, from the file
+            let archive = archive::open(std::path::Path::new(&path)).map_err(|e| {
+                let e =
+                    ApiError::Internal(format!("Failed to open speller archive {}: {:?}", path, e));
+                error!("{}", e);
+                e
+            })?;
+
+            let mut info = ModelInfo::new(&path);
+            info.archive = archive_info(&path, &*archive);
+
+            Ok::<_, ApiError>((archive, info))
+        })
+        .map_err(ApiError::from);
+
+        Box::new(opened.and_then(move |(archive, info)| {
+            let restarts = Arc::clone(&info.restarts);
+
+            let owned_language = language.clone();
+            let speller = actix::Supervisor::start_in_arbiter(&actix::Arbiter::new(), move |_| {
+                DivvunSpellExecutor {
+                    speller_archive: archive,
+                    language: owned_language,
+                    terminated: false,
+                    restarts,
+                }
+            });
+
+            let previous = spellers.write().insert(
+                language,
+                LoadedModel {
+                    executor: speller,
+                    info,
+                },
+            );
+
+            match previous {
+                // Requests already queued on the replaced speller are answered before it stops
+                Some(previous) => {
+                    Either::A(previous.executor.send(Die).then(|_| ok::<(), ApiError>(())))
+                }
+                None => Either::B(ok(())),
             }
-        });
-
-        lock.insert(language.to_owned(), speller);
-
-        Box::new(ok(()))
+        }))
     }
 
     fn remove(&self, language: &str) -> Box<dyn Future<Item = (), Error = ApiError>> {
@@ -169,9 +470,10 @@ impl LanguageSuggestions for AsyncSpeller {
         let speller = match lock.remove(language) {
             Some(s) => s,
             None => {
-                return Box::new(err(ApiError {
-                    message: format!("No speller available for language {}", &language),
-                }));
+                return Box::new(err(ApiError::LanguageNotFound(format!(
+                    "No speller available for language {}",
+                    &language
+                ))));
             }
         };
 
@@ -180,20 +482,27 @@ impl LanguageSuggestions for AsyncSpeller {
 
         Box::new(
             speller
+                .executor
                 .send(Die)
                 .map_err(move |err| {
                     // Put the address back in since we failed to send the die message
                     let mut lock = cloned_spellers.write();
                     lock.insert(language.clone(), speller);
 
-                    ApiError {
-                        message: format!(
-                            "Something failed in the message delivery process for language {}: {}",
-                            &language, err
-                        ),
-                    }
+                    ApiError::Unavailable(format!(
+                        "Something failed in the message delivery process for language {}: {}",
+                        &language, err
+                    ))
                 })
                 .and_then(|_| ok(())),
         )
     }
+
+    fn models(&self) -> Vec<ModelStatus> {
+        let lock = self.spellers.read();
+
+        lock.iter()
+            .map(|(language, speller)| speller.info.status(language, speller.executor.connected()))
+            .collect()
+    }
 }
@@ -0,0 +1,125 @@
+use std::cmp::Ordering;
+
+use super::data_files::{iso639_alias, split_variant};
+
+/// Lists the tags a requested BCP 47 language tag may be served by, from the most to the least
+/// specific. Subtags are dropped from the end one at a time, as in RFC 4647 lookup, and each
+/// candidate is also tried with the other ISO 639 tag of its language, so that `sme-Latn-NO`
+/// falls back through `se-latn-no`, `sme-latn`, `se-latn` and `sme` to `se`.
+pub fn fallback_chain(tag: &str) -> Vec<String> {
+    let tag = tag.trim().replace('_', "-").to_lowercase();
+    let subtags: Vec<&str> = tag.split('-').filter(|subtag| !subtag.is_empty()).collect();
+
+    let mut chain: Vec<String> = vec![];
+
+    for end in (1..=subtags.len()).rev() {
+        // A tag can't end with a singleton, such as the `x` introducing private use subtags
+        if end > 1 && subtags[end - 1].len() == 1 {
+            continue;
+        }
+
+        let language = subtags[0];
+
+        for candidate_language in std::iter::once(language).chain(iso639_alias(language)) {
+            let candidate = std::iter::once(candidate_language)
+                .chain(subtags[1..end].iter().cloned())
+                .collect::<Vec<_>>()
+                .join("-");
+
+            if !chain.contains(&candidate) {
+                chain.push(candidate);
+            }
+        }
+    }
+
+    chain
+}
+
+/// Finds the loaded model key that best serves a requested language tag, ignoring case
+pub fn resolve<'a, I>(keys: I, tag: &str) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let keys: Vec<&str> = keys.into_iter().collect();
+
+    fallback_chain(tag).iter().find_map(|candidate| {
+        keys.iter()
+            .find(|key| key.eq_ignore_ascii_case(candidate))
+            .cloned()
+    })
+}
+
+/// Like `resolve`, but only considers the languages of the keys, leaving out their variants
+pub fn resolve_language<'a, I>(keys: I, tag: &str) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    resolve(keys.into_iter().map(|key| split_variant(key).0), tag)
+}
+
+/// Lists the tags of an `Accept-Language` header from the most to the least preferred, leaving
+/// out `*` and tags with a quality of 0
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut tags: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let tag = params.next()?.trim();
+            let quality = params
+                .filter_map(|param| {
+                    let param = param.trim();
+                    if param.starts_with("q=") {
+                        param[2..].trim().parse::<f32>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+
+            if tag.is_empty() || tag == "*" || quality <= 0.0 {
+                None
+            } else {
+                Some((tag, quality))
+            }
+        })
+        .collect();
+
+    // The sort is stable, so tags of equal quality stay in the order they were given
+    tags.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+    tags.into_iter().map(|(tag, _)| tag.to_owned()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let keys = vec!["se", "smj", "nb", "se-x-school"];
+
+        assert_eq!(resolve(keys.clone(), "se"), Some("se"));
+        assert_eq!(resolve(keys.clone(), "SE"), Some("se"));
+        assert_eq!(resolve(keys.clone(), "sme"), Some("se"));
+        assert_eq!(resolve(keys.clone(), "nb-NO"), Some("nb"));
+        assert_eq!(resolve(keys.clone(), "nob_NO"), Some("nb"));
+        assert_eq!(resolve(keys.clone(), "smj-Latn-SE"), Some("smj"));
+        assert_eq!(resolve(keys.clone(), "se-x-school"), Some("se-x-school"));
+        assert_eq!(resolve(keys.clone(), "sme-x-school"), Some("se-x-school"));
+        assert_eq!(resolve(keys.clone(), "se-x-unknown"), Some("se"));
+        assert_eq!(resolve(keys.clone(), "fi"), None);
+        assert_eq!(resolve(keys.clone(), ""), None);
+
+        assert_eq!(resolve_language(vec!["sma-x-old"], "SMA-SE"), Some("sma"));
+    }
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(
+            parse_accept_language("nb-NO;q=0.8, se, en;q=0.5, *;q=0.1, fi;q=0"),
+            vec!["se", "nb-NO", "en"]
+        );
+        assert_eq!(parse_accept_language(""), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The position of a token within the submitted text, counted in bytes, Unicode scalar values
+/// and UTF-16 code units so that clients on any platform can locate it
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct TextOffsets {
+    pub bytes: Span,
+    pub chars: Span,
+    pub utf16: Span,
+}
+
+/// Converts byte indices into `TextOffsets`. Indices must be supplied in increasing order, as
+/// produced by `word_indices()`, so the text is only walked once.
+pub struct OffsetIndexer<'a> {
+    text: &'a str,
+    byte: usize,
+    chars: usize,
+    utf16: usize,
+}
+
+impl<'a> OffsetIndexer<'a> {
+    pub fn new(text: &'a str) -> Self {
+        OffsetIndexer {
+            text,
+            byte: 0,
+            chars: 0,
+            utf16: 0,
+        }
+    }
+
+    pub fn offsets(&mut self, byte_index: usize, word: &str) -> TextOffsets {
+        let skipped = &self.text[self.byte..byte_index];
+        self.chars += skipped.chars().count();
+        self.utf16 += utf16_len(skipped);
+        self.byte = byte_index;
+
+        TextOffsets {
+            bytes: Span {
+                start: byte_index,
+                end: byte_index + word.len(),
+            },
+            chars: Span {
+                start: self.chars,
+                end: self.chars + word.chars().count(),
+            },
+            utf16: Span {
+                start: self.utf16,
+                end: self.utf16 + utf16_len(word),
+            },
+        }
+    }
+}
+
+pub fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_offsets() {
+        let text = "ođas 𝄞 ođas";
+        let mut indexer = OffsetIndexer::new(text);
+
+        let first = indexer.offsets(0, "ođas");
+        assert_eq!(first.bytes, Span { start: 0, end: 5 });
+        assert_eq!(first.chars, Span { start: 0, end: 4 });
+        assert_eq!(first.utf16, Span { start: 0, end: 4 });
+
+        let last = indexer.offsets(11, "ođas");
+        assert_eq!(last.bytes, Span { start: 11, end: 16 });
+        assert_eq!(last.chars, Span { start: 7, end: 11 });
+        assert_eq!(last.utf16, Span { start: 8, end: 12 });
+    }
+}
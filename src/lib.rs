@@ -4,5 +4,6 @@ pub mod file_utils;
 pub mod graphql;
 pub mod init;
 pub mod language;
+pub mod metrics;
 pub mod server;
 pub mod watcher;
@@ -0,0 +1,159 @@
+use std::time::Instant;
+
+use futures::future::Future;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+
+use crate::error::ApiError;
+use crate::language::data_files::DataFileType;
+use crate::language::models::ModelStatus;
+use crate::server::state::State;
+
+/// The `language` label of requests for tags that resolve to no loaded model. Requested tags are
+/// supplied by clients, so only the tags of loaded models are used as label values.
+const UNKNOWN_LANGUAGE: &str = "unknown";
+
+lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "divvun_api_requests_total",
+        "Number of checking requests handled",
+        &["endpoint", "language"]
+    )
+    .unwrap();
+    static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "divvun_api_request_duration_seconds",
+        "Time taken to handle checking requests",
+        &["endpoint", "language"]
+    )
+    .unwrap();
+    static ref REQUEST_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "divvun_api_request_errors_total",
+        "Number of checking requests that failed, by error code",
+        &["endpoint", "language", "code"]
+    )
+    .unwrap();
+    static ref MAILBOX_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "divvun_api_executor_mailbox_depth",
+        "Number of requests sent to the executors of a model that have not completed yet",
+        &["type", "language"]
+    )
+    .unwrap();
+    pub static ref HYPHENATION_DURATION: HistogramVec = register_histogram_vec!(
+        "divvun_api_hyphenation_lookup_duration_seconds",
+        "Time taken to look up the hyphenation patterns of a whole request",
+        &["language"]
+    )
+    .unwrap();
+    static ref LOADED_MODELS: IntGaugeVec = register_int_gauge_vec!(
+        "divvun_api_loaded_models",
+        "Number of loaded models",
+        &["type"]
+    )
+    .unwrap();
+    static ref MODEL_RESTARTS: IntCounterVec = register_int_counter_vec!(
+        "divvun_api_model_restarts_total",
+        "Number of times the executors of a model were restarted, including divvun-checker children",
+        &["type", "language"]
+    )
+    .unwrap();
+}
+
+/// Counts a request in the mailbox depth of a model for as long as it is alive. It is moved into
+/// the future of the request, so that requests whose future is dropped before completing are
+/// taken off the gauge as well.
+pub struct Queued(IntGauge);
+
+impl Queued {
+    pub fn new(data_type: DataFileType, language: &str) -> Self {
+        let depth = MAILBOX_DEPTH.with_label_values(&[data_type.as_dir(), language]);
+        depth.inc();
+        Queued(depth)
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Records the count, latency and outcome of a checking request for the model `model_key`, as
+/// resolved before sending the request to it
+pub fn track<F>(
+    endpoint: &'static str,
+    model_key: Option<String>,
+    future: F,
+) -> impl Future<Item = F::Item, Error = ApiError>
+where
+    F: Future<Error = ApiError>,
+{
+    let start = Instant::now();
+
+    future.then(move |result| {
+        let language = model_key.as_ref().map_or(UNKNOWN_LANGUAGE, String::as_str);
+
+        REQUESTS.with_label_values(&[endpoint, language]).inc();
+        REQUEST_DURATION
+            .with_label_values(&[endpoint, language])
+            .observe(start.elapsed().as_secs_f64());
+
+        if let Err(e) = &result {
+            REQUEST_ERRORS
+                .with_label_values(&[endpoint, language, e.code()])
+                .inc();
+        }
+
+        result
+    })
+}
+
+/// Renders every metric in the Prometheus text format
+pub fn render(state: &State) -> Result<(String, Vec<u8>), ApiError> {
+    let language_functions = &state.language_functions;
+
+    update_model_metrics(
+        DataFileType::Spelling,
+        language_functions.spelling_suggestions.models(),
+    );
+    update_model_metrics(
+        DataFileType::Grammar,
+        language_functions.grammar_suggestions.models(),
+    );
+    update_model_metrics(
+        DataFileType::Hyphenation,
+        language_functions.hyphenation_suggestions.models(),
+    );
+
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+
+    encoder
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(|e| ApiError::Internal(format!("Failed to encode metrics: {}", e)))?;
+
+    Ok((encoder.format_type().to_owned(), buffer))
+}
+
+fn update_model_metrics(data_type: DataFileType, models: Vec<ModelStatus>) {
+    let type_label = data_type.as_dir();
+
+    LOADED_MODELS
+        .with_label_values(&[type_label])
+        .set(models.len() as i64);
+}
+
+/// Counts a restart of an executor of a model, including divvun-checker children
+pub fn count_restart(data_type: DataFileType, language: &str) {
+    MODEL_RESTARTS
+        .with_label_values(&[data_type.as_dir(), language])
+        .inc();
+}
+
+/// Stops reporting the restarts of a model once it is unloaded
+pub fn remove_model(data_type: DataFileType, language: &str) {
+    // Models that were never restarted have no restart count to remove
+    let _ = MODEL_RESTARTS.remove_label_values(&[data_type.as_dir(), language]);
+}
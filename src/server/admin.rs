@@ -0,0 +1,207 @@
+use std::path::Path;
+
+use futures::future::{err, Future};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::language::data_files::DataFileType;
+use crate::language::models::ModelStatus;
+use crate::server::state::State;
+
+#[derive(Deserialize, Debug)]
+pub struct LoadModelRequest {
+    pub path: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModelActionResponse {
+    pub action: &'static str,
+    #[serde(rename = "type")]
+    pub data_type: &'static str,
+    pub language: String,
+    /// Status of the model after the action, absent once it is unloaded
+    pub model: Option<ModelStatus>,
+}
+
+/// Checks a bearer token against the configured `admin_token`. The admin API is disabled when
+/// no token is configured.
+pub fn authorize(state: &State, authorization: Option<&str>) -> Result<(), ApiError> {
+    let expected = match &state.config.admin_token {
+        Some(token) => token,
+        None => {
+            return Err(ApiError::Unauthorized(
+                "The admin API is disabled, set `admin_token` to enable it".to_owned(),
+            ))
+        }
+    };
+
+    match authorization.and_then(bearer_token) {
+        Some(given) if constant_time_eq(given.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(ApiError::Unauthorized(
+            "Missing or invalid admin token".to_owned(),
+        )),
+    }
+}
+
+/// Loads the model at `path` for `language`, replacing a model already loaded for it without
+/// interrupting requests to it
+pub fn load_model(
+    state: &State,
+    data_type: DataFileType,
+    language: &str,
+    path: &str,
+) -> Box<dyn Future<Item = ModelActionResponse, Error = ApiError>> {
+    info!(
+        "Admin request to load {} model {} for {}",
+        data_type.as_dir(),
+        path,
+        language
+    );
+
+    if let Err(e) = validate_path(data_type, path) {
+        return Box::new(err(e));
+    }
+
+    let state = state.clone();
+    let language = language.to_owned();
+
+    // A loaded model is only replaced once the new one has loaded successfully
+    Box::new(
+        state
+            .add_model(data_type, &language, path)
+            .map(move |_| ModelActionResponse {
+                action: "load",
+                data_type: data_type.as_dir(),
+                model: find_model(&state, data_type, &language),
+                language,
+            }),
+    )
+}
+
+pub fn unload_model(
+    state: &State,
+    data_type: DataFileType,
+    language: &str,
+) -> Box<dyn Future<Item = ModelActionResponse, Error = ApiError>> {
+    info!(
+        "Admin request to unload {} model for {}",
+        data_type.as_dir(),
+        language
+    );
+
+    let language = language.to_owned();
+
+    Box::new(
+        state
+            .remove_model(data_type, &language)
+            .map(move |_| ModelActionResponse {
+                action: "unload",
+                data_type: data_type.as_dir(),
+                language,
+                model: None,
+            }),
+    )
+}
+
+/// Loads a language's model again from the file it was loaded from
+pub fn reload_model(
+    state: &State,
+    data_type: DataFileType,
+    language: &str,
+) -> Box<dyn Future<Item = ModelActionResponse, Error = ApiError>> {
+    let path = match find_model(state, data_type, language) {
+        Some(model) => model.path,
+        None => {
+            return Box::new(err(ApiError::LanguageNotFound(format!(
+                "No {} model loaded for language {}",
+                data_type.as_dir(),
+                language
+            ))))
+        }
+    };
+
+    Box::new(
+        load_model(state, data_type, language, &path).map(|response| ModelActionResponse {
+            action: "reload",
+            ..response
+        }),
+    )
+}
+
+fn find_model(state: &State, data_type: DataFileType, language: &str) -> Option<ModelStatus> {
+    state
+        .models(data_type)
+        .into_iter()
+        .find(|model| model.language == language)
+}
+
+fn validate_path(data_type: DataFileType, path: &str) -> Result<(), ApiError> {
+    let path = Path::new(path);
+
+    if !path.is_file() {
+        return Err(ApiError::UnprocessableInput(format!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+
+    if !data_type.matches(path) {
+        return Err(ApiError::UnprocessableInput(format!(
+            "{} models must have one of the extensions .{}",
+            data_type.as_dir(),
+            data_type.as_exts().join(", .")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Takes the token out of an `Authorization` header value, which must use the `Bearer` scheme
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let mut parts = authorization.trim().splitn(2, ' ');
+
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("Bearer") => Some(token.trim()),
+        _ => None,
+    }
+}
+
+/// Compares every byte of `expected` whatever `given` is, so that the time taken doesn't tell
+/// how much of a guessed token was right
+fn constant_time_eq(given: &[u8], expected: &[u8]) -> bool {
+    let difference = expected
+        .iter()
+        .enumerate()
+        .fold(given.len() ^ expected.len(), |acc, (i, byte)| {
+            acc | usize::from(given.get(i).copied().unwrap_or(0) ^ byte)
+        });
+
+    difference == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer secret"), Some("secret"));
+        assert_eq!(bearer_token("bearer  secret "), Some("secret"));
+        assert_eq!(bearer_token("BEARER secret"), Some("secret"));
+
+        assert_eq!(bearer_token("Basic secret"), None);
+        assert_eq!(bearer_token("Foo secret"), None);
+        assert_eq!(bearer_token("secret"), None);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+
+        assert!(!constant_time_eq(b"secreT", b"secret"));
+        assert!(!constant_time_eq(b"secre", b"secret"));
+        assert!(!constant_time_eq(b"secrets", b"secret"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
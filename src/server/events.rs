@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use actix_web::web::Bytes;
+use futures::stream::Stream;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio_timer::Interval;
+
+use crate::error::{ApiError, ApiErrorResponse};
+use crate::language::data_files::DataFileType;
+
+/// How often a comment is sent on idle event streams, so that proxies don't close them
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum ModelEventKind {
+    ModelLoaded,
+    /// A model was loaded for a language that already had one, which it replaced
+    ModelReloaded,
+    ModelUnloaded,
+    ModelFailed,
+}
+
+impl ModelEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelEventKind::ModelLoaded => "ModelLoaded",
+            ModelEventKind::ModelReloaded => "ModelReloaded",
+            ModelEventKind::ModelUnloaded => "ModelUnloaded",
+            ModelEventKind::ModelFailed => "ModelFailed",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelEvent {
+    pub event: ModelEventKind,
+    #[serde(rename = "type")]
+    pub data_type: &'static str,
+    pub language: String,
+    /// The file the model was loaded from, or failed to load from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiErrorResponse>,
+}
+
+impl ModelEvent {
+    pub fn new(event: ModelEventKind, data_type: DataFileType, language: &str) -> Self {
+        ModelEvent {
+            event,
+            data_type: data_type.as_dir(),
+            language: language.to_owned(),
+            path: None,
+            error: None,
+        }
+    }
+
+    /// Formats the event as a Server-Sent Event named after its kind
+    fn to_sse(&self) -> Bytes {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        Bytes::from(format!(
+            "event: {}\ndata: {}\n\n",
+            self.event.as_str(),
+            data
+        ))
+    }
+}
+
+/// Hands model events to every open event stream
+#[derive(Default)]
+pub struct Events {
+    subscribers: Mutex<Vec<UnboundedSender<ModelEvent>>>,
+}
+
+impl Events {
+    pub fn subscribe(&self) -> UnboundedReceiver<ModelEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    /// Sends the event to the current subscribers, dropping those whose stream has closed
+    pub fn publish(&self, event: ModelEvent) {
+        log::debug!("Model event {:?}", &event);
+
+        self.subscribers
+            .lock()
+            .retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// The body of an `/events` response: the events as they happen, with heartbeats between
+    pub fn stream(&self) -> impl Stream<Item = Bytes, Error = ApiError> {
+        let events = self
+            .subscribe()
+            .map(|event| event.to_sse())
+            .map_err(|_| ApiError::Internal("The event stream closed".to_owned()));
+
+        let heartbeats = Interval::new(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL)
+            .map(|_| Bytes::from_static(b":\n\n"))
+            .map_err(|e| ApiError::Internal(e.to_string()));
+
+        // Sent at once so that clients know the stream is open before the first event
+        let opened = futures::stream::once(Ok(Bytes::from_static(b"retry: 5000\n\n")));
+
+        opened.chain(events.select(heartbeats))
+    }
+}
@@ -0,0 +1,181 @@
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::future::{result, Future};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::error::ApiError;
+use crate::language::data_files::DataFileType;
+use crate::language::models::ModelStatus;
+use crate::metrics;
+use crate::server::admin::{self, LoadModelRequest, ModelActionResponse};
+use crate::server::state::State;
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    /// Data files present on disk that have no running model
+    pub missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct StatusResponse {
+    pub speller: Vec<ModelStatus>,
+    pub grammar: Vec<ModelStatus>,
+    pub hyphenation: Vec<ModelStatus>,
+}
+
+pub fn health_handler() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+pub fn readiness_handler(state: web::Data<State>) -> HttpResponse {
+    let status = model_status(&state);
+
+    let missing: Vec<String> = [
+        (DataFileType::Spelling, &status.speller),
+        (DataFileType::Grammar, &status.grammar),
+        (DataFileType::Hyphenation, &status.hyphenation),
+    ]
+    .iter()
+    .flat_map(|(data_type, models)| {
+        state
+            .config
+            .data_files(*data_type)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|path| {
+                let language = path.file_stem().and_then(|stem| stem.to_str());
+
+                !models
+                    .iter()
+                    .any(|model| Some(model.language.as_str()) == language && model.alive)
+            })
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+    })
+    .collect();
+
+    let response = ReadinessResponse {
+        ready: missing.is_empty(),
+        missing,
+    };
+
+    if response.ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
+pub fn status_handler(state: web::Data<State>) -> HttpResponse {
+    HttpResponse::Ok().json(model_status(&state))
+}
+
+/// Streams model load and unload events as Server-Sent Events
+pub fn events_handler(state: web::Data<State>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .streaming(state.events.stream())
+}
+
+pub fn metrics_handler(state: web::Data<State>) -> Result<HttpResponse, ApiError> {
+    let (content_type, body) = metrics::render(&state)?;
+
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
+
+pub fn admin_models_handler(
+    req: HttpRequest,
+    state: web::Data<State>,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&req, &state)?;
+
+    Ok(HttpResponse::Ok().json(model_status(&state)))
+}
+
+pub fn admin_load_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    body: web::Json<LoadModelRequest>,
+    state: web::Data<State>,
+) -> impl Future<Item = HttpResponse, Error = ApiError> {
+    admin_action(&req, &path, &state, move |state, data_type, language| {
+        admin::load_model(state, data_type, language, &body.path)
+    })
+}
+
+pub fn admin_unload_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    state: web::Data<State>,
+) -> impl Future<Item = HttpResponse, Error = ApiError> {
+    admin_action(&req, &path, &state, admin::unload_model)
+}
+
+pub fn admin_reload_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    state: web::Data<State>,
+) -> impl Future<Item = HttpResponse, Error = ApiError> {
+    admin_action(&req, &path, &state, admin::reload_model)
+}
+
+fn admin_action<F>(
+    req: &HttpRequest,
+    path: &(String, String),
+    state: &State,
+    action: F,
+) -> Box<dyn Future<Item = HttpResponse, Error = ApiError>>
+where
+    F: FnOnce(
+        &State,
+        DataFileType,
+        &str,
+    ) -> Box<dyn Future<Item = ModelActionResponse, Error = ApiError>>,
+{
+    let (data_type, language) = path;
+
+    let data_type = match authorize(req, state).and_then(|_| parse_data_type(data_type)) {
+        Ok(data_type) => data_type,
+        Err(e) => return Box::new(result(Err(e))),
+    };
+
+    // Models are loaded on the blocking thread pool by their backends, so the action doesn't
+    // hold up the worker
+    Box::new(action(state, data_type, language).map(|response| HttpResponse::Ok().json(response)))
+}
+
+fn authorize(req: &HttpRequest, state: &State) -> Result<(), ApiError> {
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    admin::authorize(state, authorization)
+}
+
+fn parse_data_type(data_type: &str) -> Result<DataFileType, ApiError> {
+    DataFileType::from_dir(data_type).ok_or_else(|| {
+        ApiError::InvalidInput(format!(
+            "Unknown model type {}, expected spelling, grammar or hyphenation",
+            data_type
+        ))
+    })
+}
+
+fn model_status(state: &State) -> StatusResponse {
+    let language_functions = &state.language_functions;
+
+    StatusResponse {
+        speller: sorted(language_functions.spelling_suggestions.models()),
+        grammar: sorted(language_functions.grammar_suggestions.models()),
+        hyphenation: sorted(language_functions.hyphenation_suggestions.models()),
+    }
+}
+
+fn sorted(mut models: Vec<ModelStatus>) -> Vec<ModelStatus> {
+    models.sort_by(|a, b| a.language.cmp(&b.language));
+    models
+}
@@ -2,40 +2,80 @@ use std::env;
 
 use actix_cors::Cors;
 use actix_web::dev::Server;
-use actix_web::{http::header, middleware, web, App, HttpServer};
+use actix_web::error::JsonPayloadError;
+use actix_web::{http::header, middleware, web, App, HttpRequest, HttpServer};
 
+pub mod admin;
+pub mod events;
+pub mod handlers;
 pub mod state;
 
+use self::handlers::{
+    admin_load_handler, admin_models_handler, admin_reload_handler, admin_unload_handler,
+    events_handler, health_handler, metrics_handler, readiness_handler, status_handler,
+};
 use self::state::State;
 use crate::config::Config;
+use crate::error::ApiError;
 use crate::graphql::handlers::{graphiql, graphql};
 
 use crate::language::handlers::{
-    get_available_languages_handler, get_gramcheck_preferences_handler, gramchecker_handler,
-    hyphenation_handler, speller_handler,
+    batch_handler, get_available_languages_handler, get_gramcheck_preferences_handler,
+    gramchecker_handler, hyphenation_handler, session_handler, speller_handler,
+    speller_info_handler,
 };
 
+fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        // Valid JSON that doesn't fit the request type
+        JsonPayloadError::Deserialize(ref e) if e.is_data() => {
+            ApiError::UnprocessableInput(e.to_string()).into()
+        }
+        e => ApiError::InvalidInput(e.to_string()).into(),
+    }
+}
+
+fn cors(origins: &[String]) -> Cors {
+    let cors = if origins.is_empty() {
+        Cors::new().send_wildcard()
+    } else {
+        origins
+            .iter()
+            .fold(Cors::new(), |cors, origin| cors.allowed_origin(origin))
+    };
+
+    cors.allowed_methods(vec!["POST", "GET", "PUT", "DELETE"])
+        .allowed_headers(vec![header::ACCEPT, header::AUTHORIZATION])
+        .allowed_header(header::CONTENT_TYPE)
+        .max_age(3600)
+}
+
 pub fn start_server(state: State, config: &Config) -> Server {
     env::set_var("RUST_BACKTRACE", "1");
 
+    let cors_origins = config.cors_origins.clone();
+    let max_body_bytes = config.max_body_bytes;
+
     HttpServer::new(move || {
         App::new()
             .data(state.clone())
-            .wrap(middleware::Logger::default())
-            .wrap(
-                Cors::new()
-                    .send_wildcard()
-                    .allowed_methods(vec!["POST", "GET"])
-                    .allowed_headers(vec![header::ACCEPT])
-                    .allowed_header(header::CONTENT_TYPE)
-                    .max_age(3600),
+            .data(
+                web::JsonConfig::default()
+                    .limit(max_body_bytes)
+                    .error_handler(json_error_handler),
             )
+            .wrap(middleware::Logger::default())
+            .wrap(cors(&cors_origins))
             .service(web::resource("/graphiql").route(web::get().to(graphiql)))
             .service(web::resource("/graphql").route(web::post().to_async(graphql)))
             .service(
                 web::resource("/speller/{languageCode}")
                     .route(web::post().to_async(speller_handler)),
             )
+            .service(
+                web::resource("/speller/{languageCode}/info")
+                    .route(web::get().to(speller_info_handler)),
+            )
             .service(
                 web::resource("/grammar/{languageCode}")
                     .route(web::post().to_async(gramchecker_handler)),
@@ -48,11 +88,28 @@ pub fn start_server(state: State, config: &Config) -> Server {
                 web::resource("/preferences/grammar/{languageCode}")
                     .route(web::get().to_async(get_gramcheck_preferences_handler)),
             )
+            .service(web::resource("/session").route(web::get().to(session_handler)))
+            .service(web::resource("/batch").route(web::post().to_async(batch_handler)))
             .service(
                 web::resource("/languages").route(web::get().to(get_available_languages_handler)),
             )
+            .service(web::resource("/health").route(web::get().to(health_handler)))
+            .service(web::resource("/ready").route(web::get().to(readiness_handler)))
+            .service(web::resource("/status").route(web::get().to(status_handler)))
+            .service(web::resource("/events").route(web::get().to(events_handler)))
+            .service(web::resource("/metrics").route(web::get().to(metrics_handler)))
+            .service(web::resource("/admin/models").route(web::get().to(admin_models_handler)))
+            .service(
+                web::resource("/admin/models/{type}/{languageCode}")
+                    .route(web::put().to_async(admin_load_handler))
+                    .route(web::delete().to_async(admin_unload_handler)),
+            )
+            .service(
+                web::resource("/admin/models/{type}/{languageCode}/reload")
+                    .route(web::post().to_async(admin_reload_handler)),
+            )
     })
-    .workers(4)
+    .workers(config.workers)
     .bind(&config.addr)
     .unwrap()
     .start()
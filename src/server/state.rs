@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use actix::Addr;
+use actix_web::web;
 use futures::future::{err, ok, Future};
 use hashbrown::HashMap;
 use parking_lot::RwLock;
@@ -12,16 +12,15 @@ use crate::error::ApiError;
 use crate::file_utils::get_file_info;
 use crate::graphql::schema::create_schema;
 use crate::graphql::schema::Schema;
-use crate::language::data_files::{get_data_files, DataFileType};
+use crate::language::data_files::DataFileType;
 use crate::language::grammar::{
-    list_preferences, AsyncGramchecker, GramcheckExecutor, GramcheckRequest, GramcheckResponse,
-};
-use crate::language::hyphenation::{
-    AsyncHyphenator, HyphenationExecutor, HyphenationRequest, HyphenationResponse,
-};
-use crate::language::speller::{
-    AsyncSpeller, DivvunSpellExecutor, SpellerRequest, SpellerResponse,
+    list_preferences, AsyncGramchecker, GramcheckRequest, GramcheckResponse,
 };
+use crate::language::hyphenation::{AsyncHyphenator, HyphenationRequest, HyphenationResponse};
+use crate::language::models::ModelStatus;
+use crate::language::speller::{AsyncSpeller, SpellerRequest, SpellerResponse};
+use crate::metrics;
+use crate::server::events::{Events, ModelEvent, ModelEventKind};
 
 pub struct LanguageFunctions {
     pub spelling_suggestions:
@@ -41,8 +40,11 @@ pub trait LanguageSuggestions: Send + Sync {
         message: Self::Request,
         language: &str,
     ) -> Box<dyn Future<Item = Self::Response, Error = ApiError>>;
+    /// The key of the loaded model a request for `language` would be sent to, if any
+    fn model_key(&self, message: &Self::Request, language: &str) -> Option<String>;
     fn add(&self, language: &str, path: &str) -> Box<dyn Future<Item = (), Error = ApiError>>;
     fn remove(&self, language: &str) -> Box<dyn Future<Item = (), Error = ApiError>>;
+    fn models(&self) -> Vec<ModelStatus>;
 }
 
 pub trait UnhoistFutureExt<U, E> {
@@ -68,10 +70,143 @@ pub struct InnerState {
     pub graphql_schema: Schema,
     pub language_functions: LanguageFunctions,
     pub gramcheck_preferences: Arc<RwLock<HashMap<String, BTreeMap<String, String>>>>,
+    pub events: Arc<Events>,
+}
+
+impl InnerState {
+    /// Loads a model, replacing the one loaded for the language without interrupting it
+    pub fn add_model(
+        &self,
+        data_type: DataFileType,
+        language: &str,
+        path: &str,
+    ) -> Box<dyn Future<Item = (), Error = ApiError>> {
+        let loaded = self
+            .models(data_type)
+            .iter()
+            .any(|model| model.language == language);
+
+        let kind = if loaded {
+            ModelEventKind::ModelReloaded
+        } else {
+            ModelEventKind::ModelLoaded
+        };
+
+        let event = ModelEvent {
+            path: Some(path.to_owned()),
+            ..ModelEvent::new(kind, data_type, language)
+        };
+        let events = Arc::clone(&self.events);
+
+        Box::new(
+            self.load_model(data_type, language, path)
+                .then(move |result| {
+                    match &result {
+                        Ok(_) => events.publish(event),
+                        Err(e) => events.publish(ModelEvent {
+                            event: ModelEventKind::ModelFailed,
+                            error: Some(e.to_response()),
+                            ..event
+                        }),
+                    }
+
+                    result
+                }),
+        )
+    }
+
+    pub fn remove_model(
+        &self,
+        data_type: DataFileType,
+        language: &str,
+    ) -> Box<dyn Future<Item = (), Error = ApiError>> {
+        let event = ModelEvent::new(ModelEventKind::ModelUnloaded, data_type, language);
+        let events = Arc::clone(&self.events);
+        let language = language.to_owned();
+
+        Box::new(self.unload_model(data_type, &language).map(move |_| {
+            metrics::remove_model(data_type, &language);
+            events.publish(event)
+        }))
+    }
+
+    fn load_model(
+        &self,
+        data_type: DataFileType,
+        language: &str,
+        path: &str,
+    ) -> Box<dyn Future<Item = (), Error = ApiError>> {
+        let language_functions = &self.language_functions;
+
+        match data_type {
+            DataFileType::Spelling => language_functions.spelling_suggestions.add(language, path),
+            DataFileType::Grammar => {
+                let owned_path = path.to_owned();
+                let preferences = web::block(move || {
+                    list_preferences(&owned_path).map_err(|e| {
+                        ApiError::Internal(format!(
+                            "Failed to retrieve grammar preferences from {}: {}",
+                            owned_path, e
+                        ))
+                    })
+                })
+                .map_err(ApiError::from);
+
+                let added = language_functions.grammar_suggestions.add(language, path);
+
+                // The checker only takes over once its preferences have been read as well
+                let gramcheck_preferences = Arc::clone(&self.gramcheck_preferences);
+                let language = language.to_owned();
+                Box::new(preferences.and_then(move |preferences| {
+                    added.map(move |_| {
+                        gramcheck_preferences.write().insert(language, preferences);
+                    })
+                }))
+            }
+            DataFileType::Hyphenation => language_functions
+                .hyphenation_suggestions
+                .add(language, path),
+        }
+    }
+
+    fn unload_model(
+        &self,
+        data_type: DataFileType,
+        language: &str,
+    ) -> Box<dyn Future<Item = (), Error = ApiError>> {
+        let language_functions = &self.language_functions;
+
+        match data_type {
+            DataFileType::Spelling => language_functions.spelling_suggestions.remove(language),
+            DataFileType::Grammar => {
+                let removed = language_functions.grammar_suggestions.remove(language);
+
+                let gramcheck_preferences = Arc::clone(&self.gramcheck_preferences);
+                let language = language.to_owned();
+                Box::new(removed.map(move |_| {
+                    gramcheck_preferences.write().remove(&language);
+                }))
+            }
+            DataFileType::Hyphenation => {
+                language_functions.hyphenation_suggestions.remove(language)
+            }
+        }
+    }
+
+    pub fn models(&self, data_type: DataFileType) -> Vec<ModelStatus> {
+        let language_functions = &self.language_functions;
+
+        match data_type {
+            DataFileType::Spelling => language_functions.spelling_suggestions.models(),
+            DataFileType::Grammar => language_functions.grammar_suggestions.models(),
+            DataFileType::Hyphenation => language_functions.hyphenation_suggestions.models(),
+        }
+    }
 }
 
 pub fn create_state(config: &Config) -> State {
-    let grammar_data_files = get_data_files(config.data_file_dir.as_path(), DataFileType::Grammar)
+    let grammar_data_files = config
+        .data_files(DataFileType::Grammar)
         .unwrap_or_else(|e| {
             log::error!("Error getting grammar data files: {}", e);
             vec![]
@@ -82,49 +217,48 @@ pub fn create_state(config: &Config) -> State {
         graphql_schema: create_schema(),
         language_functions: LanguageFunctions {
             spelling_suggestions: Box::new(get_speller(config)),
-            grammar_suggestions: Box::new(get_gramchecker(&grammar_data_files)),
+            grammar_suggestions: Box::new(get_gramchecker(config, &grammar_data_files)),
             hyphenation_suggestions: Box::new(get_hyphenation(config)),
         },
         gramcheck_preferences: Arc::new(RwLock::new(get_gramcheck_preferences(
             &grammar_data_files,
         ))),
+        events: Arc::new(Events::default()),
     })
 }
 
 fn get_speller(config: &Config) -> AsyncSpeller {
-    let spelling_data_files =
-        get_data_files(config.data_file_dir.as_path(), DataFileType::Spelling).unwrap_or_else(
-            |e| {
-                log::error!("Error getting spelling data files: {}", e);
-                vec![]
-            },
-        );
+    let spelling_data_files = config
+        .data_files(DataFileType::Spelling)
+        .unwrap_or_else(|e| {
+            log::error!("Error getting spelling data files: {}", e);
+            vec![]
+        });
 
     let speller = AsyncSpeller {
-        spellers: Arc::new(RwLock::new(
-            HashMap::<String, Addr<DivvunSpellExecutor>>::new(),
-        )),
+        spellers: Arc::new(RwLock::new(HashMap::new())),
     };
 
     for file in spelling_data_files {
         if let Some(file_info) = get_file_info(&file) {
-            speller.add(file_info.stem, file_info.path);
+            // A file that fails to load is logged by `add` and leaves its language out
+            let _ = speller.add(file_info.stem, file_info.path).wait();
         }
     }
 
     speller
 }
 
-fn get_gramchecker(grammar_data_files: &Vec<PathBuf>) -> AsyncGramchecker {
+fn get_gramchecker(config: &Config, grammar_data_files: &Vec<PathBuf>) -> AsyncGramchecker {
     let gramchecker = AsyncGramchecker {
-        gramcheckers: Arc::new(RwLock::new(
-            HashMap::<String, Addr<GramcheckExecutor>>::new(),
-        )),
+        gramcheckers: Arc::new(RwLock::new(HashMap::new())),
+        pool_config: config.grammar_pool.clone(),
     };
 
     for file in grammar_data_files {
         if let Some(file_info) = get_file_info(&file) {
-            gramchecker.add(file_info.stem, file_info.path);
+            // A file that fails to load is logged by `add` and leaves its language out
+            let _ = gramchecker.add(file_info.stem, file_info.path).wait();
         }
     }
 
@@ -132,23 +266,21 @@ fn get_gramchecker(grammar_data_files: &Vec<PathBuf>) -> AsyncGramchecker {
 }
 
 fn get_hyphenation(config: &Config) -> AsyncHyphenator {
-    let hyphenation_data_files =
-        get_data_files(config.data_file_dir.as_path(), DataFileType::Hyphenation).unwrap_or_else(
-            |e| {
-                log::error!("Error getting hyphenation data files: {}", e);
-                vec![]
-            },
-        );
+    let hyphenation_data_files = config
+        .data_files(DataFileType::Hyphenation)
+        .unwrap_or_else(|e| {
+            log::error!("Error getting hyphenation data files: {}", e);
+            vec![]
+        });
 
     let hyphenator = AsyncHyphenator {
-        hyphenators: Arc::new(RwLock::new(
-            HashMap::<String, Addr<HyphenationExecutor>>::new(),
-        )),
+        hyphenators: Arc::new(RwLock::new(HashMap::new())),
     };
 
     for file in hyphenation_data_files {
         if let Some(file_info) = get_file_info(&file) {
-            hyphenator.add(file_info.stem, file_info.path);
+            // A file that fails to load is logged by `add` and leaves its language out
+            let _ = hyphenator.add(file_info.stem, file_info.path).wait();
         }
     }
 
@@ -160,13 +292,21 @@ fn get_gramcheck_preferences(
 ) -> HashMap<String, BTreeMap<String, String>> {
     let gramcheck_preferences = grammar_data_files
         .into_iter()
-        .map(|f| {
+        .filter_map(|f| {
             let grammar_checker_path = f.to_str().unwrap();
             let lang_code = f.file_stem().unwrap().to_str().unwrap();
-            (
-                lang_code.into(),
-                list_preferences(grammar_checker_path).unwrap(),
-            )
+
+            match list_preferences(grammar_checker_path) {
+                Ok(preferences) => Some((lang_code.into(), preferences)),
+                Err(e) => {
+                    log::error!(
+                        "Failed to retrieve grammar preferences from {}: {}",
+                        grammar_checker_path,
+                        e
+                    );
+                    None
+                }
+            }
         })
         .collect();
 
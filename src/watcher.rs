@@ -1,16 +1,24 @@
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::channel;
 use std::time::Duration;
 
 use actix::prelude::*;
-use log::{error, info};
+use futures::future::Future;
+use log::{error, info, warn};
 use notify::Watcher as _;
-use notify::{watcher, DebouncedEvent, RecursiveMode};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode};
 
 use crate::file_utils::get_file_info;
-use crate::language::data_files::{get_typed_data_dir, DataFileType};
-use crate::language::grammar::list_preferences;
+use crate::language::data_files::{get_data_files, preferred_data_file, DataFileType};
 use crate::server::state::State;
 
+const DATA_FILE_TYPES: [DataFileType; 3] = [
+    DataFileType::Grammar,
+    DataFileType::Spelling,
+    DataFileType::Hyphenation,
+];
+
 pub struct Watcher;
 
 impl Actor for Watcher {
@@ -25,138 +33,400 @@ impl Message for Start {
     type Result = Result<(), ()>;
 }
 
+/// The directory of one type of data file, which may not exist yet
+struct DataDir {
+    data_type: DataFileType,
+    path: PathBuf,
+    watched: bool,
+}
+
+struct DataDirs {
+    dirs: Vec<DataDir>,
+    recursive: bool,
+}
+
+impl DataDirs {
+    fn mode(&self) -> RecursiveMode {
+        if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        }
+    }
+
+    /// Starts watching the directories that exist. For those that don't, the closest existing
+    /// parent is watched instead, so that they can be picked up once they are created.
+    /// Returns the directories that started being watched.
+    fn watch(&mut self, watcher: &mut RecommendedWatcher) -> Vec<(DataFileType, PathBuf)> {
+        let mode = self.mode();
+        let mut started = vec![];
+
+        for dir in self.dirs.iter_mut().filter(|dir| !dir.watched) {
+            if dir.path.is_dir() {
+                match watcher.watch(&dir.path, mode) {
+                    Ok(_) => {
+                        info!(
+                            "Watching directory `{}` for {} files",
+                            dir.path.display(),
+                            dir.data_type.as_dir()
+                        );
+
+                        dir.path = normalize(&dir.path);
+                        dir.watched = true;
+                        started.push((dir.data_type, dir.path.clone()));
+                        continue;
+                    }
+                    Err(e) => error!("Failed to watch `{}`: {:?}", dir.path.display(), e),
+                }
+            }
+
+            if let Some(parent) = dir.path.ancestors().skip(1).find(|p| p.is_dir()) {
+                match watcher.watch(parent, RecursiveMode::NonRecursive) {
+                    Ok(_) => info!(
+                        "Directory `{}` does not exist, watching `{}` until it is created",
+                        dir.path.display(),
+                        parent.display()
+                    ),
+                    Err(e) => error!("Failed to watch `{}`: {:?}", parent.display(), e),
+                }
+            }
+        }
+
+        started
+    }
+
+    /// The type of data file at `path`, if it is one in a watched directory
+    fn data_type(&self, path: &Path) -> Option<DataFileType> {
+        let path = normalize(path);
+
+        self.dirs
+            .iter()
+            .filter(|dir| dir.watched)
+            .find(|dir| {
+                let in_dir = if self.recursive {
+                    path.starts_with(&dir.path)
+                } else {
+                    path.parent() == Some(dir.path.as_path())
+                };
+
+                in_dir && dir.data_type.matches(&path)
+            })
+            .map(|dir| dir.data_type)
+    }
+
+    /// The type of data files in the directory at `path`, if it is inside a watched directory
+    fn subdir_type(&self, path: &Path) -> Option<DataFileType> {
+        let path = normalize(path);
+
+        self.dirs
+            .iter()
+            .filter(|dir| dir.watched)
+            .find(|dir| path.starts_with(&dir.path) && path != dir.path)
+            .map(|dir| dir.data_type)
+    }
+}
+
 impl Handler<Start> for Watcher {
     type Result = Result<(), ()>;
 
     fn handle(&mut self, msg: Start, _: &mut Self::Context) -> Self::Result {
         let state = msg.state;
-        let data_file_dir = &state.config.data_file_dir;
+        let config = &state.config;
 
         let (tx, rx) = channel();
 
-        let interval = state.config.watcher_interval_ms;
-        let mut watcher = watcher(tx, Duration::from_millis(interval)).unwrap();
-
-        let dir = get_typed_data_dir(data_file_dir.as_path(), DataFileType::Grammar);
-        watcher
-            .watch(
-                get_typed_data_dir(data_file_dir.as_path(), DataFileType::Grammar),
-                RecursiveMode::NonRecursive,
-            )
-            .unwrap();
-        info!("Watching directory `{}` for grammar files", dir.display());
-
-        let dir = get_typed_data_dir(data_file_dir.as_path(), DataFileType::Spelling);
-        watcher
-            .watch(
-                get_typed_data_dir(data_file_dir.as_path(), DataFileType::Spelling),
-                RecursiveMode::NonRecursive,
-            )
-            .unwrap();
-        info!("Watching directory `{}` for speller files", dir.display());
-
-        let dir = get_typed_data_dir(data_file_dir.as_path(), DataFileType::Hyphenation);
-        watcher
-            .watch(
-                get_typed_data_dir(data_file_dir.as_path(), DataFileType::Hyphenation),
-                RecursiveMode::NonRecursive,
-            )
-            .unwrap();
-        info!(
-            "Watching directory `{}` for hyphenation files",
-            dir.display()
-        );
+        let interval = config.watcher_interval_ms;
+        let mut watcher = watcher(tx, Duration::from_millis(interval)).map_err(|e| {
+            error!("Failed to start watching data files: {:?}", e);
+        })?;
 
-        loop {
-            match rx.recv() {
-                Ok(event) => match &event {
-                    DebouncedEvent::Create(path) => {
-                        info!("Event {:?}", &event);
-
-                        if let Some(file_info) = get_file_info(path) {
-                            if file_info.extension == DataFileType::Grammar.as_ext() {
-                                let preferences = match list_preferences(file_info.path) {
-                                    Ok(preferences) => preferences,
-                                    Err(e) => {
-                                        error!("Failed to retrieve grammar preferences for {}: {}, ignoring file", e, file_info.stem);
-                                        continue;
-                                    }
-                                };
-
-                                let grammar_checkers =
-                                    &state.language_functions.grammar_suggestions;
-                                grammar_checkers.add(file_info.stem, file_info.path);
-
-                                let prefs_lock = &mut state.gramcheck_preferences.write();
-                                prefs_lock.insert(file_info.stem.to_owned(), preferences);
-                            } else if file_info.extension == DataFileType::Spelling.as_ext() {
-                                let spellers = &state.language_functions.spelling_suggestions;
-                                spellers.add(file_info.stem, file_info.path);
-                            } else if file_info.extension == DataFileType::Hyphenation.as_ext() {
-                                let hyphenators = &state.language_functions.hyphenation_suggestions;
-                                hyphenators.add(file_info.stem, file_info.path);
-                            }
+        let mut dirs = DataDirs {
+            dirs: DATA_FILE_TYPES
+                .iter()
+                .map(|&data_type| {
+                    let path = config.data_dir(data_type);
+
+                    if !path.exists() {
+                        match fs::create_dir_all(&path) {
+                            Ok(_) => info!("Created missing directory `{}`", path.display()),
+                            Err(e) => warn!(
+                                "Failed to create missing directory `{}`: {}",
+                                path.display(),
+                                e
+                            ),
                         }
                     }
-                    DebouncedEvent::Remove(path) => {
-                        info!("Event {:?}", &event);
-
-                        if let Some(file_info) = get_file_info(path) {
-                            if file_info.extension == DataFileType::Grammar.as_ext() {
-                                let grammar_checkers =
-                                    &state.language_functions.grammar_suggestions;
-                                grammar_checkers.remove(file_info.stem);
-
-                                let prefs_lock = &mut state.gramcheck_preferences.write();
-                                prefs_lock.remove(file_info.stem);
-                            } else if file_info.extension == DataFileType::Spelling.as_ext() {
-                                let spellers = &state.language_functions.spelling_suggestions;
-                                spellers.remove(file_info.stem);
-                            } else if file_info.extension == DataFileType::Hyphenation.as_ext() {
-                                let hyphenators = &state.language_functions.hyphenation_suggestions;
-                                hyphenators.remove(file_info.stem);
-                            }
-                        }
+
+                    DataDir {
+                        data_type,
+                        path,
+                        watched: false,
                     }
-                    DebouncedEvent::Write(path) => {
-                        info!("Event {:?}", &event);
-
-                        if let Some(file_info) = get_file_info(path) {
-                            if file_info.extension == DataFileType::Grammar.as_ext() {
-                                let preferences = match list_preferences(file_info.path) {
-                                    Ok(preferences) => preferences,
-                                    Err(e) => {
-                                        error!("Failed to retrieve grammar preferences for {}: {}, ignoring file", e, file_info.stem);
-                                        continue;
-                                    }
-                                };
-
-                                let grammar_checkers =
-                                    &state.language_functions.grammar_suggestions;
-
-                                grammar_checkers.remove(file_info.stem);
-                                grammar_checkers.add(file_info.stem, file_info.path);
-
-                                let prefs_lock = &mut state.gramcheck_preferences.write();
-                                prefs_lock.remove(file_info.stem);
-                                prefs_lock.insert(file_info.stem.to_owned(), preferences);
-                            } else if file_info.extension == DataFileType::Spelling.as_ext() {
-                                let spellers = &state.language_functions.spelling_suggestions;
-
-                                spellers.remove(file_info.stem);
-                                spellers.add(file_info.stem, file_info.path);
-                            } else if file_info.extension == DataFileType::Hyphenation.as_ext() {
-                                let hyphenators = &state.language_functions.hyphenation_suggestions;
-
-                                hyphenators.remove(file_info.stem);
-                                hyphenators.add(file_info.stem, file_info.path);
-                            }
+                })
+                .collect(),
+            recursive: config.recursive_data_dirs,
+        };
+
+        dirs.watch(&mut watcher);
+
+        loop {
+            let event = match rx.recv() {
+                Ok(event) => event,
+                Err(e) => {
+                    error!("Watch error: {:?}", e);
+                    return Err(());
+                }
+            };
+
+            info!("Event {:?}", &event);
+
+            match &event {
+                DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                    if path.is_dir() {
+                        // A data directory that was missing may just have been created, or a
+                        // directory of models moved into a watched one
+                        for (data_type, dir) in dirs.watch(&mut watcher) {
+                            load_dir(&state, data_type, &dir, dirs.recursive);
                         }
+
+                        if let (true, Some(data_type)) = (dirs.recursive, dirs.subdir_type(path)) {
+                            load_dir(&state, data_type, path, true);
+                        }
+                    } else if let Some(data_type) = dirs.data_type(path) {
+                        load(&state, data_type, path);
+                    }
+                }
+                DebouncedEvent::Remove(path) => {
+                    if let Some(data_type) = dirs.data_type(path) {
+                        unload(&state, data_type, path);
                     }
-                    _ => info!("Event {:?}", &event),
-                },
-                Err(e) => error!("Watch error: {:?}", e),
+                }
+                DebouncedEvent::Rename(from, to) => {
+                    let from_type = dirs.data_type(from);
+                    let to_type = dirs.data_type(to);
+
+                    if let Some(data_type) = to_type {
+                        load(&state, data_type, to);
+                    }
+
+                    // Nothing is unloaded when the renamed file has replaced the model, as it
+                    // is no longer loaded from the old path
+                    if let Some(data_type) = from_type {
+                        unload(&state, data_type, from);
+                    }
+                }
+                DebouncedEvent::Error(e, path) => error!("Watch error: {:?} {:?}", e, path),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn load_dir(state: &State, data_type: DataFileType, dir: &Path, recursive: bool) {
+    for path in get_data_files(dir, data_type, recursive).unwrap_or_default() {
+        load(state, data_type, &path);
+    }
+}
+
+fn load(state: &State, data_type: DataFileType, path: &Path) {
+    let path = path.to_path_buf();
+
+    // A model that exists in several formats is only loaded from the preferred one
+    if let Some(preferred) = preferred_data_file(&path, data_type) {
+        if preferred != path {
+            info!(
+                "Not loading `{}`, `{}` is loaded instead",
+                path.display(),
+                preferred.display()
+            );
+            return;
+        }
+    }
+
+    if let Some(file_info) = get_file_info(&path) {
+        // Models are keyed by the file stem alone, so a file with the same name in another
+        // subdirectory doesn't replace the model loaded from the first one
+        if let Some(loaded) = loaded_from_other_dir(state, data_type, file_info.stem, &path) {
+            warn!(
+                "Not loading `{}`, `{}` is loaded for the same language",
+                path.display(),
+                loaded.display()
+            );
+            return;
+        }
+
+        // The running model, if any, is only replaced once the new file has loaded
+        if let Err(e) = state
+            .add_model(data_type, file_info.stem, file_info.path)
+            .wait()
+        {
+            error!(
+                "Failed to load {} model for {}: {}",
+                data_type.as_dir(),
+                file_info.stem,
+                e
+            );
+        }
+    }
+}
+
+fn unload(state: &State, data_type: DataFileType, path: &Path) {
+    let path = path.to_path_buf();
+
+    if let Some(file_info) = get_file_info(&path) {
+        // Only unload the model if it was loaded from this file, as another file may have
+        // been loaded for the language since
+        let loaded_from_path = state.models(data_type).iter().any(|model| {
+            model.language == file_info.stem
+                && normalize(Path::new(&model.path)) == normalize(&path)
+        });
+
+        if !loaded_from_path {
+            return;
+        }
+
+        // If the model is still there in another format, that one takes over
+        if let Some(other) = preferred_data_file(&path, data_type) {
+            load(state, data_type, &other);
+            return;
+        }
+
+        if let Err(e) = state.remove_model(data_type, file_info.stem).wait() {
+            error!(
+                "Failed to unload {} model for {}: {}",
+                data_type.as_dir(),
+                file_info.stem,
+                e
+            );
+        }
+    }
+}
+
+/// The file the model for `language` is loaded from, if it still exists in another directory
+/// than `path`
+fn loaded_from_other_dir(
+    state: &State,
+    data_type: DataFileType,
+    language: &str,
+    path: &Path,
+) -> Option<PathBuf> {
+    let loaded = state
+        .models(data_type)
+        .into_iter()
+        .find(|model| model.language == language)
+        .map(|model| normalize(Path::new(&model.path)))?;
+
+    if loaded.is_file() && loaded.parent() != normalize(path).parent() {
+        Some(loaded)
+    } else {
+        None
+    }
+}
+
+/// Makes paths comparable whether they were given relative or absolute, including paths of
+/// files that no longer exist
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match (
+        path.parent().and_then(|parent| parent.canonicalize().ok()),
+        path.file_name(),
+    ) {
+        (Some(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_dirs(path: &Path, recursive: bool) -> DataDirs {
+        DataDirs {
+            dirs: vec![DataDir {
+                data_type: DataFileType::Spelling,
+                path: path.to_path_buf(),
+                watched: false,
+            }],
+            recursive,
+        }
+    }
+
+    #[test]
+    fn test_missing_dir_watched_once_created() {
+        let root = std::env::temp_dir().join("divvun-api-test-missing-data-dir");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let spelling = root.join("spelling");
+        let (tx, rx) = channel();
+        let mut watcher = watcher(tx, Duration::from_millis(50)).unwrap();
+        let mut dirs = data_dirs(&spelling, false);
+
+        // The missing directory is waited for by watching its parent
+        assert!(dirs.watch(&mut watcher).is_empty());
+        assert_eq!(dirs.data_type(&spelling.join("se.zhfst")), None);
+
+        fs::create_dir(&spelling).unwrap();
+
+        let mut created = false;
+        while let Ok(event) = rx.recv_timeout(Duration::from_secs(5)) {
+            if let DebouncedEvent::Create(path) = event {
+                if normalize(&path) == normalize(&spelling) {
+                    created = true;
+                    break;
+                }
             }
         }
+        assert!(created, "No event for the created directory");
+
+        let spelling = normalize(&spelling);
+        assert_eq!(
+            dirs.watch(&mut watcher),
+            vec![(DataFileType::Spelling, spelling.clone())]
+        );
+        assert_eq!(
+            dirs.data_type(&spelling.join("se.zhfst")),
+            Some(DataFileType::Spelling)
+        );
+        assert!(dirs.watch(&mut watcher).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_nested_files() {
+        let root = std::env::temp_dir().join("divvun-api-test-nested-data-dir");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("se")).unwrap();
+
+        let nested = root.join("se").join("se.zhfst");
+        let (tx, _rx) = channel();
+        let mut watcher = watcher(tx, Duration::from_millis(50)).unwrap();
+
+        let mut flat = data_dirs(&root, false);
+        flat.watch(&mut watcher);
+        assert_eq!(
+            flat.data_type(&root.join("se.zhfst")),
+            Some(DataFileType::Spelling)
+        );
+        assert_eq!(flat.data_type(&nested), None);
+
+        let mut recursive = data_dirs(&root, true);
+        recursive.watch(&mut watcher);
+        assert_eq!(recursive.data_type(&nested), Some(DataFileType::Spelling));
+        assert_eq!(
+            recursive.data_type(&root.join("se").join("se.zcheck")),
+            None
+        );
+        assert_eq!(
+            recursive.subdir_type(&root.join("se")),
+            Some(DataFileType::Spelling)
+        );
+        assert_eq!(recursive.subdir_type(&root), None);
+
+        fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -8,7 +8,7 @@ use std::path::PathBuf;
 use std::{env, thread, time};
 
 use divvun_api::config::Config;
-use divvun_api::error::ApiError;
+use divvun_api::error::ApiErrorResponse;
 use divvun_api::init::{init_config, init_system};
 use divvun_api::language::grammar::GramcheckResponse;
 use divvun_api::language::hyphenation::HyphenationResponse;
@@ -20,52 +20,57 @@ use steps::watcher;
 
 static TEST_DATA_FILES: &'static str = "tests/resources/data_files";
 static TEST_WATCHER_INTERVAL: u64 = 500;
+static TEST_ADMIN_TOKEN: &'static str = "test-admin-token";
 
 pub struct MyWorld {
     config: Config,
     json: serde_json::Value,
+    text: String,
     speller_response: Option<SpellerResponse>,
     grammar_response: Option<GramcheckResponse>,
     hyphenation_response: Option<HyphenationResponse>,
-    api_error: Option<ApiError>,
+    api_error: Option<ApiErrorResponse>,
+    status: Option<u16>,
 }
 
 impl cucumber_rust::World for MyWorld {}
 
 impl Default for MyWorld {
     fn default() -> MyWorld {
-        let toml_config = init_config();
-
-        let config = Config {
-            addr: toml_config.addr,
-            data_file_dir: PathBuf::from(TEST_DATA_FILES),
-            watcher_interval_ms: TEST_WATCHER_INTERVAL,
-        };
+        let config = test_config();
 
         // This function is called every time a new scenario is started
         MyWorld {
             json: json!(""),
+            text: String::new(),
             config: config.clone(),
             speller_response: None,
             grammar_response: None,
             hyphenation_response: None,
             api_error: None,
+            status: None,
         }
     }
 }
 
+fn test_config() -> Config {
+    let mut config = init_config().expect("Invalid test configuration");
+    config.data_file_dir = PathBuf::from(TEST_DATA_FILES);
+    config.spelling_dir = None;
+    config.grammar_dir = None;
+    config.hyphenation_dir = None;
+    config.watcher_interval_ms = TEST_WATCHER_INTERVAL;
+    config.admin_token = Some(TEST_ADMIN_TOKEN.to_owned());
+
+    config
+}
+
 // A setup function to be called before everything else
 fn setup() {
     env::set_var("RUST_LOG", "info");
     env_logger::init();
 
-    let toml_config = init_config();
-
-    let config = Config {
-        addr: toml_config.addr,
-        data_file_dir: PathBuf::from(TEST_DATA_FILES),
-        watcher_interval_ms: TEST_WATCHER_INTERVAL,
-    };
+    let config = test_config();
 
     std::thread::spawn(move || {
         let (_app, system) = init_system(&config);
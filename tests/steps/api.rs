@@ -1,7 +1,7 @@
-use divvun_api::error::ApiError;
 use divvun_api::language::grammar::GramcheckResponse;
 use divvun_api::language::hyphenation::HyphenationResponse;
 use divvun_api::language::speller::SpellerResponse;
+use reqwest::Method;
 
 steps!(crate::MyWorld => {
     given "I have loaded `se` grammar, speller, and hyphenator files" |world, _step| {
@@ -25,11 +25,252 @@ steps!(crate::MyWorld => {
     };
 
     then "I get back a JSON object with available languages and their titles" |world, _step| {
-        assert_eq!(&world.json, &json!({"available":{
+        assert_eq!(&world.json["available"], &json!({
             "grammar": {"se": "davvisámegiella"},
             "hyphenation": {"se": "davvisámegiella"},
             "speller": {"se" :"davvisámegiella"}
-        }}));
+        }));
+
+        let variants = world.json["variants"]["se"].as_array().unwrap();
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0]["tag"], "se");
+        assert_eq!(variants[0]["default"], true);
+    };
+
+    then regex r"^I get back metadata for the `([^`]*)` language named `([^`]*)`$" (String, String) |world, language, name, _step| {
+        let languages = world.json["languages"].as_array().unwrap();
+        let info = languages.iter().find(|info| info["tag"] == language.as_str()).unwrap();
+
+        assert_eq!(info["tag1"], "se");
+        assert_eq!(info["tag3"], "sme");
+        assert_eq!(info["name"], "Northern Sami");
+        assert_eq!(info["autonym"], "davvisámegiella");
+        assert_eq!(info["localized_name"], name.as_str());
+        assert_eq!(info["capabilities"], json!(["grammar", "spelling", "hyphenation"]));
+
+        let models = info["models"].as_array().unwrap();
+        assert_eq!(models.len(), 3);
+
+        let speller = models.iter().find(|model| model["type"] == "spelling").unwrap();
+        assert_eq!(speller["tag"], "se");
+        assert!(speller["modified"].as_u64().unwrap() > 0);
+    };
+
+    then regex r"^I get back info about the `([^`]*)` speller$" (String) |world, language, _step| {
+        assert_eq!(world.json["language"], language.as_str());
+        assert_eq!(world.json["variant"], json!(null));
+        assert!(world.json["size"].as_u64().unwrap() > 0);
+        assert!(world.json["loaded_at"].as_u64().unwrap() > 0);
+    };
+
+    then regex r"^I get back a JSON object with the status `([^`]*)`$" (String) |world, status, _step| {
+        assert_eq!(&world.json, &json!({ "status": status }));
+    };
+
+    then "I get back a JSON object saying the server is ready" |world, _step| {
+        assert_eq!(&world.json, &json!({ "ready": true, "missing": [] }));
+    };
+
+    then "I get back a JSON object with a running `se` speller, grammar checker and hyphenator" |world, _step| {
+        for (kind, file) in &[
+            ("speller", "spelling/se.zhfst"),
+            ("grammar", "grammar/se.zcheck"),
+            ("hyphenation", "hyphenation/se.hfstol"),
+        ] {
+            let models = world.json[kind].as_array().unwrap();
+            let model = models.iter().find(|model| model["language"] == "se").unwrap();
+
+            assert_eq!(model["alive"], true);
+            assert_eq!(model["restarts"], 0);
+            assert!(model["path"].as_str().unwrap().ends_with(file));
+            assert!(model["size"].as_u64().unwrap() > 0);
+        }
+    };
+
+    when "I go to the metrics endpoint" |world, _step| {
+        let url = format!("http://{}/metrics", &world.config.addr);
+        world.text = reqwest::get(&url).unwrap().text().unwrap();
+    };
+
+    then regex r"^I get back metrics with the request count for the `([^`]*)` endpoint and `([^`]*)` language$" (String, String) |world, endpoint, language, _step| {
+        let prefix = format!(
+            "divvun_api_requests_total{{endpoint=\"{}\",language=\"{}\"}}",
+            endpoint, language
+        );
+
+        assert!(world.text.lines().any(|line| line.starts_with(&prefix)), "{}", &world.text);
+        assert!(world.text.contains("divvun_api_loaded_models{type=\"spelling\"}"));
+    };
+
+    then regex r"^I get back metrics with a `([^`]*)` error for the `([^`]*)` endpoint and `([^`]*)` language$" (String, String, String) |world, code, endpoint, language, _step| {
+        let prefix = format!(
+            "divvun_api_request_errors_total{{code=\"{}\",endpoint=\"{}\",language=\"{}\"}}",
+            code, endpoint, language
+        );
+
+        assert!(world.text.lines().any(|line| line.starts_with(&prefix)), "{}", &world.text);
+    };
+
+    when regex r"^I get the admin endpoint `([^`]*)` without a token$" (String) |world, endpoint, _step| {
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let mut response = reqwest::get(&url).unwrap();
+        world.status = Some(response.status().as_u16());
+        world.api_error = Some(response.json().unwrap());
+    };
+
+    when regex r"^I post to the admin endpoint `([^`]*)`$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+        let token = world.config.admin_token.clone().unwrap();
+
+        let mut response = client.post(&url).bearer_auth(token).send().unwrap();
+        world.status = Some(response.status().as_u16());
+        world.json = response.json().unwrap();
+    };
+
+    when regex r"^I post to the admin endpoint `([^`]*)` with the token as `([^`]*)` credentials$" (String, String) |world, endpoint, scheme, _step| {
+        let token = world.config.admin_token.clone().unwrap();
+        admin_request(world, Method::POST, &endpoint, &format!("{} {}", scheme, token), None);
+    };
+
+    when regex r"^I put the model file `([^`]*)` to the admin endpoint `([^`]*)`$" (String, String) |world, path, endpoint, _step| {
+        let token = world.config.admin_token.clone().unwrap();
+        admin_request(world, Method::PUT, &endpoint, &format!("Bearer {}", token), Some(json!({"path": path})));
+    };
+
+    when regex r"^I put the model file `([^`]*)` to the admin endpoint `([^`]*)` with a wrong token$" (String, String) |world, path, endpoint, _step| {
+        admin_request(world, Method::PUT, &endpoint, "Bearer wrong-token", Some(json!({"path": path})));
+    };
+
+    when regex r"^I delete the admin endpoint `([^`]*)`$" (String) |world, endpoint, _step| {
+        let token = world.config.admin_token.clone().unwrap();
+        admin_request(world, Method::DELETE, &endpoint, &format!("Bearer {}", token), None);
+    };
+
+    when regex r"^I delete the admin endpoint `([^`]*)` with the token as `([^`]*)` credentials$" (String, String) |world, endpoint, scheme, _step| {
+        let token = world.config.admin_token.clone().unwrap();
+        admin_request(world, Method::DELETE, &endpoint, &format!("{} {}", scheme, token), None);
+    };
+
+    when regex r"^I post to the admin endpoint `([^`]*)` while listening to `/events`$" (String) |world, endpoint, _step| {
+        use std::io::{BufRead, BufReader};
+
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()
+            .unwrap();
+        let events = client.get(&format!("http://{}/events", &world.config.addr)).send().unwrap();
+        assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+        let token = world.config.admin_token.clone().unwrap();
+        let response = client.post(&url).bearer_auth(token).send().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        // Reads up to the first event, skipping the retry interval and heartbeats
+        let mut lines = BufReader::new(events).lines();
+        let event = lines.by_ref()
+            .map(|line| line.unwrap())
+            .find(|line| line.starts_with("event: "))
+            .unwrap();
+        let data = lines.next().unwrap().unwrap();
+
+        world.text = event["event: ".len()..].to_owned();
+        world.json = serde_json::from_str(&data["data: ".len()..]).unwrap();
+    };
+
+    then regex r"^I receive a `([^`]*)` event for the `([^`]*)` (\w+) model$" (String, String, String) |world, event, language, data_type, _step| {
+        assert_eq!(world.text, event);
+        assert_eq!(world.json["event"], event.as_str());
+        assert_eq!(world.json["type"], data_type.as_str());
+        assert_eq!(world.json["language"], language.as_str());
+        assert!(world.json["path"].as_str().unwrap().ends_with(".zhfst"));
+    };
+
+    when regex r"^I open a check session for `([^`]*)` and append a misspelled word$" (String) |world, language, _step| {
+        use awc::ws::{Frame, Message};
+        use futures::future::{loop_fn, Future, Loop};
+        use futures::{Sink, Stream};
+
+        let url = format!("ws://{}/session", &world.config.addr);
+        let messages = vec![
+            json!({"type": "open", "language": language, "text": "Mun lean\nbuorre"}),
+            json!({"type": "edit", "offset": 15, "delete": 0, "insert": " pákhat"}),
+        ];
+
+        // Reads messages until the speller results of the edited paragraph arrive
+        let session = awc::Client::new()
+            .ws(url.as_str())
+            .connect()
+            .map_err(|e| format!("{:?}", e))
+            .and_then(move |(_response, framed)| {
+                framed
+                    .send_all(futures::stream::iter_ok(
+                        messages.into_iter().map(|message| Message::Text(message.to_string())),
+                    ))
+                    .map_err(|e| format!("{:?}", e))
+            })
+            .and_then(|(framed, _)| {
+                loop_fn((framed, vec![]), |(framed, mut received)| {
+                    framed
+                        .into_future()
+                        .map_err(|(e, _)| format!("{:?}", e))
+                        .and_then(|(frame, framed)| match frame {
+                            Some(Frame::Text(Some(bytes))) => {
+                                let message: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                                let done = message["type"] == "speller"
+                                    && message["version"] == 1
+                                    && message["paragraph"] == 1;
+                                received.push(message);
+
+                                if done {
+                                    Ok(Loop::Break(received))
+                                } else {
+                                    Ok(Loop::Continue((framed, received)))
+                                }
+                            }
+                            Some(_) => Ok(Loop::Continue((framed, received))),
+                            None => Err("The session closed".to_owned()),
+                        })
+                })
+            });
+
+        let received = actix::System::new("session").block_on(session).unwrap();
+        world.json = serde_json::Value::Array(received);
+    };
+
+    then "I get back the speller results of the edited paragraph only" |world, _step| {
+        let received = world.json.as_array().unwrap();
+
+        assert_eq!(received[0], json!({"type": "opened", "version": 0, "paragraphs": 2}));
+        assert!(received.contains(&json!({
+            "type": "edited", "version": 1, "start": 1, "removed": 1, "inserted": 1
+        })));
+
+        let speller = received.last().unwrap();
+        assert_eq!(speller["paragraph"], 1);
+        assert_eq!(speller["language"], "se");
+
+        let results = speller["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["word"], "pákhat");
+        assert_eq!(results[0]["offsets"]["utf16"], json!({"start": 7, "end": 13}));
+        assert_ne!(results[0]["suggestions"].as_array().unwrap().len(), 0);
+    };
+
+    then regex r"^I get back a `([^`]*)` model action with a running `([^`]*)` model$" (String, String) |world, action, language, _step| {
+        assert_eq!(world.status, Some(200));
+        assert_eq!(world.json["action"], action.as_str());
+        assert_eq!(world.json["language"], language.as_str());
+        assert_eq!(world.json["model"]["alive"], true);
+    };
+
+    then regex r"^I get back an `unload` model action for the `([^`]*)` model$" (String) |world, language, _step| {
+        assert_eq!(world.status, Some(200));
+        assert_eq!(world.json["action"], "unload");
+        assert_eq!(world.json["language"], language.as_str());
+        assert_eq!(world.json["model"], serde_json::Value::Null);
     };
 
     when regex r"^I go to the endpoint `([^`]*)` with appropriate data$" |world, matches, _step| {
@@ -59,6 +300,7 @@ steps!(crate::MyWorld => {
     then "I get back a SpellerResponse with suggestions for each word" |world, _step| {
         let response = &world.speller_response.clone().unwrap();
         assert_eq!(response.text, "oainá páhkat");
+        assert_eq!(response.language, "se");
         assert_eq!(response.results.len(), 2);
 
         let oaina_res = &response.results[0];
@@ -68,14 +310,92 @@ steps!(crate::MyWorld => {
         assert_eq!(oaina_res.suggestions[0].value, "oaidná");
         assert_eq!(oaina_res.suggestions[0].weight, 18.4326171875);
 
+        assert_eq!(oaina_res.offsets.bytes.start, 0);
+        assert_eq!(oaina_res.offsets.bytes.end, 6);
+        assert_eq!(oaina_res.offsets.chars.end, 5);
+
         let pahkat_res = &response.results[1];
         assert_eq!(pahkat_res.word, "páhkat");
+        assert_eq!(pahkat_res.offsets.bytes.start, 7);
+        assert_eq!(pahkat_res.offsets.chars.start, 6);
+        assert_eq!(pahkat_res.offsets.utf16.start, 6);
         assert_eq!(pahkat_res.is_correct, true);
         assert_eq!(pahkat_res.suggestions.len() > 3, true);
         assert_eq!(pahkat_res.suggestions[0].value, "dahkat");
         assert_eq!(pahkat_res.suggestions[0].weight, 14.0126953125);
     };
 
+    when regex r"^I go to the endpoint `([^`]*)` excluding correct words$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: SpellerResponse = client.post(&url)
+            .json(&json!({"text": "oainá páhkat", "include_correct": false}))
+            .send().unwrap().json().unwrap();
+        world.speller_response = Some(response);
+    };
+
+    then "I get back a SpellerResponse with only misspelled words" |world, _step| {
+        let response = &world.speller_response.clone().unwrap();
+        assert_eq!(response.text, "oainá páhkat");
+        assert_eq!(response.results.len(), 1);
+
+        let oaina_res = &response.results[0];
+        assert_eq!(oaina_res.word, "oainá");
+        assert_eq!(oaina_res.is_correct, false);
+        assert_eq!(oaina_res.suggestions[0].value, "oaidná");
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` asking for (\d+) suggestions?$" (String, usize) |world, endpoint, count, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let mut response = client.post(&url)
+            .json(&json!({"text": "oainá páhkat", "max_suggestions": count}))
+            .send().unwrap();
+        world.status = Some(response.status().as_u16());
+
+        if response.status().is_success() {
+            world.speller_response = Some(response.json().unwrap());
+        } else {
+            world.api_error = Some(response.json().unwrap());
+        }
+    };
+
+    then regex r"^I get back a SpellerResponse with at most (\d+) suggestions? per word$" (usize) |world, count, _step| {
+        let response = &world.speller_response.clone().unwrap();
+        assert_eq!(response.results.len(), 2);
+
+        for result in &response.results {
+            assert!(result.suggestions.len() <= count);
+        }
+
+        assert_eq!(response.results[0].suggestions[0].value, "oaidná");
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` with a time budget of (\d+) ms$" (String, u64) |world, endpoint, timeout_ms, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: SpellerResponse = client.post(&url)
+            .json(&json!({"text": "oainá páhkat", "timeout_ms": timeout_ms}))
+            .send().unwrap().json().unwrap();
+        world.speller_response = Some(response);
+    };
+
+    then "I get back a SpellerResponse checking each word without suggestions" |world, _step| {
+        let response = &world.speller_response.clone().unwrap();
+
+        // The budget only cuts suggestions short, every word is still checked
+        let words: Vec<&str> = response.results.iter().map(|result| result.word.as_str()).collect();
+        assert_eq!(words, vec!["oainá", "páhkat"]);
+
+        for result in &response.results {
+            assert_eq!(result.is_correct, false);
+            assert!(result.suggestions.is_empty());
+        }
+    };
+
     then regex r"^I get back a GramcheckResponse with `([^`]*)` and `([^`]*)` error codes$" (String, String) |world, code0, code1, _step| {
         let response = &world.grammar_response.clone().unwrap();
         assert_eq!(response.text, "sup  ney");
@@ -98,33 +418,97 @@ steps!(crate::MyWorld => {
         assert_ne!(err1.suggestions.len(), 0);
     };
 
+    when regex r"^I go to the endpoint `([^`]*)` ignoring the `([^`]*)` error tag$" (String, String) |world, endpoint, tag, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: GramcheckResponse = client.post(&url)
+            .json(&json!({"text": "sup  ney", "ignore_tags": [tag]}))
+            .send().unwrap().json().unwrap();
+        world.grammar_response = Some(response);
+    };
+
+    then regex r"^I get back a GramcheckResponse with only the `([^`]*)` error code$" (String) |world, code, _step| {
+        let response = &world.grammar_response.clone().unwrap();
+        assert_eq!(response.text, "sup  ney");
+
+        let errs = &response.errs;
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].error_code, code);
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` with multiple paragraphs of data$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: GramcheckResponse = client.post(&url).json(&json!({"text": "sup  ney\n\nsup  ney"})).send().unwrap().json().unwrap();
+        world.grammar_response = Some(response);
+    };
+
+    then "I get back a GramcheckResponse with errors for each paragraph" |world, _step| {
+        let response = &world.grammar_response.clone().unwrap();
+        assert_eq!(response.text, "sup  ney\n\nsup  ney");
+
+        let errs = &response.errs;
+        assert_eq!(errs.len(), 4);
+
+        assert_eq!(errs[0].start_index, 0);
+        assert_eq!(errs[0].end_index, 8);
+
+        let err2 = &errs[2];
+        assert_eq!(err2.error_text, "sup  ney");
+        assert_eq!(err2.start_index, 10);
+        assert_eq!(err2.end_index, 18);
+    };
+
     then "I get back the correct HyphenationResponse" |world, _step| {
         let response = &world.hyphenation_response.clone().unwrap();
 
         assert_eq!(
-        json!({"text":"ođasmahttinministtar ođasmahtinministtar","results":[
-            {"word":"ođasmahttinministtar", "hyphenations":[
+        json!({"text":"ođasmahttinministtar ođasmahtinministtar","language":"se","results":[
+            {"word":"ođasmahttinministtar",
+                "offsets":{"bytes":{"start":0,"end":21},"chars":{"start":0,"end":20},"utf16":{"start":0,"end":20}},
+                "hyphenations":[
                 {"value":"o^đas^maht^tin#mi^nist^tar","weight":60.000000},
                 {"value":"o^đas^maht^tin^mi^nist^tar","weight":5000.000000}]},
-            {"word":"ođasmahtinministtar","hyphenations":[
+            {"word":"ođasmahtinministtar",
+                "offsets":{"bytes":{"start":22,"end":42},"chars":{"start":21,"end":40},"utf16":{"start":21,"end":40}},
+                "hyphenations":[
                 {"value":"o^đas^mah^tin^mi^nist^tar","weight":5000.000000}]}]}),
         serde_json::to_value(&response).unwrap());
     };
 
+    when regex r"^I go to the endpoint `(/speller/[^`]*)` with another tag for the language$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: SpellerResponse = client.post(&url).json(&json!({"text": "oainá páhkat"})).send().unwrap().json().unwrap();
+        world.speller_response = Some(response);
+    };
+
+    then regex r"^I get back a SpellerResponse from the `([^`]*)` speller$" (String) |world, language, _step| {
+        let response = &world.speller_response.clone().unwrap();
+        assert_eq!(response.language, language);
+        assert_eq!(response.results.len(), 2);
+        assert_eq!(response.results[0].suggestions[0].value, "oaidná");
+    };
+
     when regex r"^I go to the endpoint `(/speller/.*)` for not loaded language$" (String) |world, endpoint, _step| {
         let client = reqwest::Client::new();
         let url = format!("http://{}{}", &world.config.addr, endpoint);
 
-        let response: ApiError = client.post(&url).json(&json!({"text": "doesn'tmatter"})).send().unwrap().json().unwrap();
-        world.api_error = Some(response);
+        let mut response = client.post(&url).json(&json!({"text": "doesn'tmatter"})).send().unwrap();
+        world.status = Some(response.status().as_u16());
+        world.api_error = Some(response.json().unwrap());
     };
 
     when regex r"^I go to the endpoint `(/grammar/.*)` for not loaded language$" (String) |world, endpoint, _step| {
         let client = reqwest::Client::new();
         let url = format!("http://{}{}", &world.config.addr, endpoint);
 
-        let response: ApiError = client.post(&url).json(&json!({"text": "doesn't matter"})).send().unwrap().json().unwrap();
-        world.api_error = Some(response);
+        let mut response = client.post(&url).json(&json!({"text": "doesn't matter"})).send().unwrap();
+        world.status = Some(response.status().as_u16());
+        world.api_error = Some(response.json().unwrap());
     };
 
     then regex r"^I get back an ApiError with the message `([^`]*)`$" (String) |world, message, _step| {
@@ -132,6 +516,21 @@ steps!(crate::MyWorld => {
         assert_eq!(error.message, message);
     };
 
+    then regex r"^the error has the status (\d+) and the code `([^`]*)`$" (u16, String) |world, status, code, _step| {
+        assert_eq!(world.status, Some(status));
+
+        let error = &world.api_error.clone().unwrap();
+        assert_eq!(error.code, code);
+    };
+
+    when regex r"^I get the endpoint `([^`]*)` for not loaded language$" (String) |world, endpoint, _step| {
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let mut response = reqwest::get(&url).unwrap();
+        world.status = Some(response.status().as_u16());
+        world.api_error = Some(response.json().unwrap());
+    };
+
     when regex r"^I go to the endpoint `([^`]*)` with an appropriate GraphQL query$" (String) |world, endpoint, _step| {
         let client = reqwest::Client::new();
         let url = format!("http://{}{}", &world.config.addr, endpoint);
@@ -139,7 +538,7 @@ steps!(crate::MyWorld => {
         let response: serde_json::value::Value = client.post(&url)
             .json(&json!({
                 "query": "query { suggestions(text: \"pákhat\", language: \"se\") {\
-                    speller { results { word suggestions { value } } }\
+                    speller { language results { word suggestions { value } } }\
                     grammar { errs { errorText errorCode description } }\
                     hyphenation { results { hyphenations { value weight } } }\
                      } }"}))
@@ -148,6 +547,195 @@ steps!(crate::MyWorld => {
         world.json = response;
     };
 
+    when regex r"^I post a batch of texts to the endpoint `([^`]*)`$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: serde_json::value::Value = client.post(&url)
+            .json(&json!({
+                "inputs": [
+                    {"id": "A1", "text": "pákhat", "language": "se"},
+                    {"id": "A2", "text": "pákhat", "language": "sme", "options": {
+                        "speller": {"max_suggestions": 1},
+                        "hyphenation": true
+                    }},
+                    {"id": "A3", "text": "pákhat", "language": "en"}
+                ]
+            }))
+            .send().unwrap().json().unwrap();
+
+        world.json = response;
+    };
+
+    then "I get back the results of each text by its id" |world, _step| {
+        let results = &world.json["results"];
+
+        assert_eq!(results["A1"]["speller"]["language"], "se");
+        assert_eq!(results["A1"]["speller"]["results"][0]["suggestions"].as_array().unwrap().len(), 5);
+        assert_eq!(results["A1"]["hyphenation"], json!(null));
+
+        assert_eq!(results["A2"]["speller"]["results"][0]["suggestions"].as_array().unwrap().len(), 1);
+        assert_eq!(results["A2"]["hyphenation"]["results"][0]["hyphenations"][0]["value"], "pák^hat");
+
+        assert_eq!(results["A3"]["speller"], json!(null));
+        assert_eq!(results["A3"]["errors"]["speller"]["code"], "language_not_found");
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` with a GraphQL query checking a batch of texts$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: serde_json::value::Value = client.post(&url)
+            .json(&json!({
+                "query": "query {\
+                    check(inputs: [\
+                        { id: \"A1\", text: \"pákhat\", language: \"se\" },\
+                        { id: \"A2\", text: \"pákhat\", language: \"en\", options: { hyphenation: true } }\
+                    ]) { id speller { language } hyphenation { language } errors { check code } }\
+                    }"}))
+            .send().unwrap().json().unwrap();
+
+        world.json = response;
+    };
+
+    then "I get back the results of each text with its id" |world, _step| {
+        let results = world.json["data"]["check"].as_array().unwrap();
+        let result = |id: &str| results.iter().find(|result| result["id"] == id).unwrap().clone();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(result("A1"), json!(
+            {"id": "A1", "speller": {"language": "se"}, "hyphenation": null, "errors": []}
+        ));
+        assert_eq!(result("A2"), json!(
+            {"id": "A2", "speller": null, "hyphenation": null, "errors": [
+                {"check": "hyphenation", "code": "language_not_found"}
+            ]}
+        ));
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` with a GraphQL query for languages, preferences and models$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: serde_json::value::Value = client.post(&url)
+            .json(&json!({
+                "query": "query {\
+                    languages(ui: \"en\") { tag tag3 localizedName capabilities models { modelType tag } }\
+                    grammarPreferences(language: \"sme\") { language errorTags { tag description } }\
+                    models { speller { language alive } grammar { language alive } hyphenation { language alive } }\
+                    }"}))
+            .send().unwrap().json().unwrap();
+
+        world.json = response;
+    };
+
+    then "I get back the `se` language, its grammar preferences and its models" |world, _step| {
+        let data = &world.json["data"];
+        assert_ne!(data, &json!(null), "no data returned: {}", &world.json);
+
+        let languages = data["languages"].as_array().unwrap();
+        let info = languages.iter().find(|info| info["tag"] == "se").unwrap();
+        assert_eq!(info["tag3"], "sme");
+        assert_eq!(info["localizedName"], "Northern Sami");
+        assert_eq!(info["capabilities"], json!(["GRAMMAR", "SPELLING", "HYPHENATION"]));
+        assert_eq!(info["models"].as_array().unwrap().len(), 3);
+
+        assert_eq!(data["grammarPreferences"]["language"], "se");
+        assert!(data["grammarPreferences"]["errorTags"].is_array());
+
+        for kind in &["speller", "grammar", "hyphenation"] {
+            assert_eq!(data["models"][kind], json!([{ "language": "se", "alive": true }]));
+        }
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` with a GraphQL query selecting the speller twice$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: serde_json::value::Value = client.post(&url)
+            .json(&json!({
+                "query": "query { suggestions(text: \"pákhat\", language: \"se\") {\
+                    best: speller(maxSuggestions: 1) { results { suggestions { value } } }\
+                    all: speller { results { suggestions { value } } }\
+                    hyphenation { results { hyphenations { value } } }\
+                     } }"}))
+            .send().unwrap().json().unwrap();
+
+        world.json = response;
+    };
+
+    when regex r"^I go to the endpoint `([^`]*)` with a GraphQL query using variables and a fragment$" (String) |world, endpoint, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+        let response: serde_json::value::Value = client.post(&url)
+            .json(&json!({
+                "query": "query Check($text: String!, $max: Int) {\
+                    suggestions(text: $text, language: \"se\") {\
+                        ...Spellers \
+                        hyphenation @include(if: true) { results { hyphenations { value } } }\
+                    } }\
+                    fragment Spellers on Suggestions {\
+                        best: speller(maxSuggestions: $max) { results { suggestions { value } } }\
+                        all: speller { results { suggestions { value } } }\
+                    }",
+                "operationName": "Check",
+                "variables": {"text": "pákhat", "max": 1}
+            }))
+            .send().unwrap().json().unwrap();
+
+        world.json = response;
+    };
+
+    when regex r"^I send a GraphQL mutation reloading the `([^`]*)` speller with (the admin|a wrong) token$" (String, String) |world, language, token, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/graphql", &world.config.addr);
+        let token = match token.as_str() {
+            "the admin" => world.config.admin_token.clone().unwrap(),
+            _ => "wrong-token".to_owned(),
+        };
+
+        let response: serde_json::value::Value = client.post(&url)
+            .bearer_auth(token)
+            .json(&json!({
+                "query": "mutation Reload($language: String!) {\
+                    reloadModel(modelType: SPELLING, language: $language) {\
+                        action modelType language model { alive }\
+                    } }",
+                "variables": {"language": language}
+            }))
+            .send().unwrap().json().unwrap();
+
+        world.json = response;
+    };
+
+    then regex r"^I get back a `reload` GraphQL model action for the `([^`]*)` speller$" (String) |world, language, _step| {
+        assert_eq!(world.json["data"]["reloadModel"], json!({
+            "action": "reload",
+            "modelType": "SPELLING",
+            "language": language,
+            "model": {"alive": true}
+        }), "{}", &world.json);
+    };
+
+    then regex r"^I get back a GraphQL error with the code `([^`]*)`$" (String) |world, code, _step| {
+        assert_eq!(world.json["errors"][0]["extensions"]["code"], json!(code), "{}", &world.json);
+    };
+
+    then "I get back the results of both speller selections" |world, _step| {
+        let suggestions = &world.json["data"]["suggestions"];
+        assert_ne!(suggestions, &json!(null), "no data or suggestions returned");
+
+        let best = suggestions["best"]["results"][0]["suggestions"].as_array().unwrap();
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0]["value"], "pakehat");
+
+        let all = suggestions["all"]["results"][0]["suggestions"].as_array().unwrap();
+        assert_eq!(all.len(), 5);
+
+        assert_eq!(suggestions["hyphenation"]["results"][0]["hyphenations"][0]["value"], "pák^hat");
+    };
+
     then "I get back a JSON object with a Speller and Grammar, and Hyphenation response" |world, _step| {
         let suggestions = &world.json["data"]["suggestions"];
         assert_ne!(suggestions, &json!(null), "no data or suggestions returned");
@@ -172,6 +760,7 @@ steps!(crate::MyWorld => {
         }), grammar);
 
         assert_eq!(&json!({
+          "language": "se",
           "results": [{
             "word": "pákhat",
             "suggestions": [{
@@ -194,3 +783,30 @@ steps!(crate::MyWorld => {
         ]}), hyphenation);
     };
 });
+
+/// Sends a request to the admin API, keeping the body of an error response as an ApiError
+fn admin_request(
+    world: &mut crate::MyWorld,
+    method: Method,
+    endpoint: &str,
+    authorization: &str,
+    body: Option<serde_json::Value>,
+) {
+    let client = reqwest::Client::new();
+    let url = format!("http://{}{}", &world.config.addr, endpoint);
+
+    let mut request = client
+        .request(method, &url)
+        .header(reqwest::header::AUTHORIZATION, authorization);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+
+    let mut response = request.send().unwrap();
+    world.status = Some(response.status().as_u16());
+    world.json = response.json().unwrap();
+
+    if !response.status().is_success() {
+        world.api_error = Some(serde_json::from_value(world.json.clone()).unwrap());
+    }
+}
@@ -27,6 +27,39 @@ steps!(MyWorld => {
         thread::sleep(time::Duration::from_millis(sleep_time));
     };
 
+    when regex r"^I load the `([^`]*)` file into the `([^`]*)` folder as `([^`]*)`$" (String, String, String) |world, file_name, dir, new_name, _step| {
+        let mut file_path = world.config.data_file_dir.clone();
+        file_path.push(file_name);
+
+        let mut load_path = world.config.data_file_dir.clone();
+        load_path.push(dir);
+        load_path.push(new_name);
+
+        fs::copy(file_path, load_path).unwrap();
+
+        let watcher_interval = world.config.watcher_interval_ms;
+        let sleep_time = watcher_interval + 500;
+        thread::sleep(time::Duration::from_millis(sleep_time));
+    };
+
+    when regex r"^I move the `([^`]*)` file into the `([^`]*)` folder through a temporary file$" (String, String) |world, file_name, dir, _step| {
+        let mut file_path = world.config.data_file_dir.clone();
+        file_path.push(file_name.clone());
+
+        let mut load_path = world.config.data_file_dir.clone();
+        load_path.push(dir);
+        load_path.push(file_name);
+
+        let temp_path = load_path.with_extension("tmp");
+
+        fs::copy(file_path, &temp_path).unwrap();
+        fs::rename(&temp_path, load_path).unwrap();
+
+        let watcher_interval = world.config.watcher_interval_ms;
+        let sleep_time = watcher_interval + 500;
+        thread::sleep(time::Duration::from_millis(sleep_time));
+    };
+
     when regex r"^I remove the `([^`]*)` file from the `([^`]*)` folder$" (String, String) |world, file_name, dir, _step| {
         let mut load_path = world.config.data_file_dir.clone();
         load_path.push(dir);
@@ -39,6 +72,42 @@ steps!(MyWorld => {
         thread::sleep(time::Duration::from_millis(sleep_time));
     };
 
+    when regex r"^I replace the `([^`]*)` file in the `([^`]*)` folder with garbage through a temporary file$" (String, String) |world, file_name, dir, _step| {
+        let mut load_path = world.config.data_file_dir.clone();
+        load_path.push(dir);
+        load_path.push(file_name);
+
+        // The served file is left intact for the running speller, which reads from it
+        let temp_path = load_path.with_extension("tmp");
+
+        fs::write(&temp_path, b"this is not a speller archive").unwrap();
+        fs::rename(&temp_path, load_path).unwrap();
+
+        let watcher_interval = world.config.watcher_interval_ms;
+        let sleep_time = watcher_interval + 500;
+        thread::sleep(time::Duration::from_millis(sleep_time));
+    };
+
+    when regex r"^I go to the speller endpoint for `([^`]*)` with the `([^`]*)` variant$" (String, String) |world, language, variant, _step| {
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/speller/{}?variant={}", &world.config.addr, language, variant);
+
+        let response: SpellerResponse = client.post(&url).json(&json!({"text": "pákhat"})).send().unwrap().json().unwrap();
+        world.speller_response = Some(response);
+    };
+
+    then regex r"^I get back a SpellerResponse and remove the `([^`]*)` file from the `([^`]*)` folder$" (String, String) |world, file_name, dir, _step| {
+        let response = &world.speller_response.clone().unwrap();
+        assert_eq!(response.results[0].word, "pákhat");
+        assert_eq!(response.results[0].is_correct, false);
+
+        let mut load_path = world.config.data_file_dir.clone();
+        load_path.push(dir);
+        load_path.push(file_name);
+
+        fs::remove_file(load_path).unwrap();
+    };
+
     when "I go to the speller endpoint for `smj` with appropriate data" |world, _step| {
         let client = reqwest::Client::new();
         let url = format!("http://{}/speller/smj", &world.config.addr);
@@ -109,7 +178,10 @@ steps!(MyWorld => {
         load_path.push(dir);
         load_path.push(file_name);
 
-        fs::copy(file_path, load_path).unwrap();
+        let temp_path = load_path.with_extension("tmp");
+
+        fs::copy(file_path, &temp_path).unwrap();
+        fs::rename(&temp_path, load_path).unwrap();
     };
 
     then "I get back a JSON object with the `smj` language" |world, _step| {
@@ -121,10 +193,10 @@ steps!(MyWorld => {
         grammar_path.push(file_name);
 
         fs::remove_file(grammar_path).unwrap();
-        assert_eq!(&world.json, &json!({"available":{
+        assert_eq!(&world.json["available"], &json!({
             "grammar": {"se": "davvisámegiella","smj":"julevsámegiella"},
             "hyphenation": {"se": "davvisámegiella"},
             "speller": {"se": "davvisámegiella"}
-        }}));
+        }));
     };
 });